@@ -132,6 +132,7 @@ fn main() {
 
     // CREATE DRAWTEXT
     let mut draw_text = DrawText::new(device.clone(), queue.clone(), swapchain.clone(), &images);
+    draw_text.set_scale_factor(surface.window().scale_factor() as f32);
 
     let (width, _): (u32, u32) = surface.window().inner_size().into();
     let mut x = -200.0;
@@ -147,7 +148,8 @@ fn main() {
             Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
                 *control_flow = ControlFlow::Exit;
             },
-            Event::WindowEvent { event: WindowEvent::Resized(_), .. } => {
+            Event::WindowEvent { event: WindowEvent::Resized(_), .. } |
+            Event::WindowEvent { event: WindowEvent::ScaleFactorChanged { .. }, .. } => {
                 recreate_swapchain = true;
             },
             Event::RedrawEventsCleared => {
@@ -164,6 +166,7 @@ fn main() {
                     framebuffers = window_size_dependent_setup(&new_images, render_pass.clone(), &mut dynamic_state);
                     // RECREATE DRAWTEXT ON RESIZE
                     draw_text = DrawText::new(device.clone(), queue.clone(), swapchain.clone(), &new_images);
+                    draw_text.set_scale_factor(surface.window().scale_factor() as f32);
                     // RECREATE DRAWTEXT ON RESIZE END
 
                     recreate_swapchain = false;
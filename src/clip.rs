@@ -0,0 +1,56 @@
+/// A rectangle in whole screen pixels that text is clipped to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ClipRect {
+    pub min: [i32; 2],
+    pub max: [i32; 2],
+}
+
+impl ClipRect {
+    /// Edges are rounded to the nearest pixel so clipped glyphs are cut cleanly
+    fn new(x: f32, y: f32, width: f32, height: f32) -> ClipRect {
+        ClipRect {
+            min: [x.round() as i32, y.round() as i32],
+            max: [(x + width).round() as i32, (y + height).round() as i32],
+        }
+    }
+
+    fn intersection(&self, other: &ClipRect) -> ClipRect {
+        let min = [self.min[0].max(other.min[0]), self.min[1].max(other.min[1])];
+        let max = [self.max[0].min(other.max[0]), self.max[1].min(other.max[1])];
+        // keep empty rects well formed
+        ClipRect { min, max: [max[0].max(min[0]), max[1].max(min[1])] }
+    }
+
+    /// Clamps the rect to an image of the given dimensions
+    pub fn clamp(&self, dimensions: [u32; 2]) -> ClipRect {
+        self.intersection(&ClipRect { min: [0, 0], max: [dimensions[0] as i32, dimensions[1] as i32] })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max[0] <= self.min[0] || self.max[1] <= self.min[1]
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ClipStack {
+    stack: Vec<ClipRect>,
+}
+
+impl ClipStack {
+    pub fn push(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let rect = ClipRect::new(x, y, width, height);
+        let rect = match self.current() {
+            Some(current) => current.intersection(&rect),
+            None          => rect,
+        };
+        self.stack.push(rect);
+    }
+
+    pub fn pop(&mut self) {
+        self.stack.pop();
+    }
+
+    pub fn current(&self) -> Option<ClipRect> {
+        self.stack.last().cloned()
+    }
+}
@@ -0,0 +1,97 @@
+/// How the colors passed to `queue_text` are interpreted.
+///
+/// Colors are converted as needed for the format of the image being drawn to,
+/// so the same color looks the same on sRGB and UNORM swapchains.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorSpace {
+    /// Colors are sRGB encoded, like colors picked from most image editors and color pickers.
+    #[default]
+    Srgb,
+    /// Colors are linear.
+    Linear,
+}
+
+/// How text is blended with the contents of the image being drawn to.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BlendMode {
+    /// Straight alpha blending.
+    #[default]
+    Alpha,
+    /// Writes premultiplied colors and blends them with the premultiplied contents of the image, as expected by most compositors.
+    Premultiplied,
+    /// Adds the color weighted by its alpha to the image, useful for glowing text.
+    /// The alpha channel of the image is left unchanged.
+    Additive,
+}
+
+/// Controls how glyph coverage is turned into opacity before blending.
+///
+/// Blending on sRGB images happens in linear space, which makes light text on a dark background look heavier
+/// and dark text on a light background look lighter than on UNORM images.
+/// To compensate, coverage is raised to a power between `1.0 / gamma` for black text and `gamma` for white text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gamma {
+    /// Values above 1.0 make dark text heavier and light text lighter.
+    pub gamma: f32,
+    /// Pushes partial coverage away from 0.5 to sharpen glyph edges.
+    /// 0.0 leaves coverage unchanged.
+    pub contrast: f32,
+}
+
+impl Gamma {
+    /// Leaves coverage unchanged
+    pub fn none() -> Gamma {
+        Gamma { gamma: 1.0, contrast: 0.0 }
+    }
+
+    /// The default for the format of the image being drawn to, makes text look alike on sRGB and UNORM images
+    pub fn for_target(srgb: bool) -> Gamma {
+        if srgb {
+            Gamma { gamma: 2.2, contrast: 0.0 }
+        }
+        else {
+            Gamma::none()
+        }
+    }
+
+    /// Must match `adjust_coverage` in fragment.glsl
+    pub(crate) fn adjust_coverage(&self, coverage: f32, color: [f32; 4]) -> f32 {
+        let luminance = color[0] * 0.2126 + color[1] * 0.7152 + color[2] * 0.0722;
+        let exponent = 1.0 / self.gamma + (self.gamma - 1.0 / self.gamma) * luminance.clamp(0.0, 1.0);
+        let coverage = coverage.powf(exponent);
+        let coverage = coverage + (coverage - 0.5) * self.contrast;
+        coverage.clamp(0.0, 1.0)
+    }
+}
+
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    }
+    else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    }
+    else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts a color given in `color_space` to the values that need to be written to the target image.
+/// sRGB images expect linear values as the hardware encodes them, UNORM images are assumed to hold sRGB values.
+pub(crate) fn target_color(color: [f32; 4], color_space: ColorSpace, target_srgb: bool) -> [f32; 4] {
+    let convert: Option<fn(f32) -> f32> = match (color_space, target_srgb) {
+        (ColorSpace::Srgb,   true)  => Some(srgb_to_linear),
+        (ColorSpace::Linear, false) => Some(linear_to_srgb),
+        _                           => None,
+    };
+    match convert {
+        Some(convert) => [convert(color[0]), convert(color[1]), convert(color[2]), color[3]],
+        None          => color,
+    }
+}
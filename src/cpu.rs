@@ -0,0 +1,573 @@
+use crate::clip::{ClipRect, ClipStack};
+use crate::color::{self, BlendMode, ColorSpace, Gamma};
+use crate::glyph_cache::{self, GlyphCache, TextData, Quad, Antialiasing, SubpixelOrder, LCD_FILTER, CACHE_WIDTH, CACHE_HEIGHT};
+use crate::layout::{Layout, LaidOutText, Monospace, Snapping, TextStyle, Truncation};
+use crate::retained::{RetainedTexts, TextHandle};
+use crate::selection::SelectionStyle;
+use crate::tab_stops::TabStops;
+use crate::text_input::QueueText;
+use crate::transform::Transform;
+
+use std::ops::Range;
+
+/// Renders text into an RGBA8 pixel buffer without needing a vulkan device.
+///
+/// Uses the same layout and glyph cache as `DrawText` and blends glyphs the same way as the
+/// vulkan pipeline does, so the output matches what `DrawText` renders to a swapchain image.
+pub struct CpuDrawText {
+    glyph_cache:  GlyphCache,
+    width:        u32,
+    height:       u32,
+    srgb:         bool,
+    color_space:  ColorSpace,
+    gamma:        Gamma,
+    antialiasing: Antialiasing,
+    blend_mode:   BlendMode,
+    clip_stack:   ClipStack,
+    texts:        Vec<TextData>,
+    retained:     RetainedTexts<Vec<Quad>>,
+}
+
+impl CpuDrawText {
+    /// The pixels are treated like a UNORM image holding sRGB values, use `set_srgb` to blend like an sRGB image instead.
+    pub fn new(width: u32, height: u32) -> CpuDrawText {
+        CpuDrawText {
+            glyph_cache:  GlyphCache::new(),
+            width,
+            height,
+            srgb:         false,
+            color_space:  ColorSpace::default(),
+            gamma:        Gamma::for_target(false),
+            antialiasing: Antialiasing::default(),
+            blend_mode:   BlendMode::default(),
+            clip_stack:   ClipStack::default(),
+            texts:        vec!(),
+            retained:     RetainedTexts::new(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// When true the pixels are treated like an sRGB image: they are decoded before blending and encoded afterwards.
+    /// This matches what `DrawText` does on an sRGB swapchain.
+    /// Also resets the gamma to the default for the new target.
+    pub fn set_srgb(&mut self, srgb: bool) {
+        self.srgb = srgb;
+        self.gamma = Gamma::for_target(srgb);
+    }
+
+    pub fn scale_factor(&self) -> f32 {
+        self.glyph_cache.scale_factor()
+    }
+
+    /// Sets the number of pixels per logical pixel, see `DrawText::set_scale_factor`.
+    /// `width` and `height` are always in pixels.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.glyph_cache.set_scale_factor(scale_factor);
+    }
+
+    pub fn snapping(&self) -> Snapping {
+        self.glyph_cache.snapping()
+    }
+
+    /// Sets whether glyphs are moved to whole pixels, see `DrawText::set_snapping`
+    pub fn set_snapping(&mut self, snapping: Snapping) {
+        self.glyph_cache.set_snapping(snapping);
+    }
+
+    pub fn monospace(&self) -> Monospace {
+        self.glyph_cache.monospace()
+    }
+
+    /// Sets whether glyphs advance by a fixed width, see `DrawText::set_monospace`
+    pub fn set_monospace(&mut self, monospace: Monospace) {
+        self.glyph_cache.set_monospace(monospace);
+    }
+
+    pub fn tab_stops(&self) -> &TabStops {
+        self.glyph_cache.tab_stops()
+    }
+
+    /// Sets where the text after a `'\t'` is placed, see `DrawText::set_tab_stops`
+    pub fn set_tab_stops(&mut self, tab_stops: TabStops) {
+        self.glyph_cache.set_tab_stops(tab_stops);
+    }
+
+    /// The scale and position tolerances of the glyph cache
+    pub fn cache_tolerances(&self) -> (f32, f32) {
+        self.glyph_cache.tolerances()
+    }
+
+    /// Sets the tolerances used to reuse cached glyphs, see `DrawText::set_cache_tolerances`
+    pub fn set_cache_tolerances(&mut self, scale_tolerance: f32, position_tolerance: f32) {
+        self.glyph_cache.set_tolerances(scale_tolerance, position_tolerance);
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Sets how the colors passed to `queue_text` are interpreted, defaults to `ColorSpace::Srgb`
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.color_space = color_space;
+    }
+
+    pub fn gamma(&self) -> Gamma {
+        self.gamma
+    }
+
+    /// Sets how glyph coverage is adjusted, defaults to `Gamma::for_target`
+    pub fn set_gamma(&mut self, gamma: Gamma) {
+        self.gamma = gamma;
+    }
+
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.antialiasing
+    }
+
+    /// Sets how glyph edges are smoothed, defaults to `Antialiasing::Grayscale`
+    pub fn set_antialiasing(&mut self, antialiasing: Antialiasing) {
+        self.antialiasing = antialiasing;
+        self.glyph_cache.set_subpixel(antialiasing != Antialiasing::Grayscale);
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Sets how text is blended with the pixels, defaults to `BlendMode::Alpha`
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    /// Text queued until the matching `pop_clip` is only drawn inside the given rectangle, see `DrawText::push_clip`
+    pub fn push_clip(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let scale = self.glyph_cache.scale_factor();
+        self.clip_stack.push(x * scale, y * scale, width * scale, height * scale);
+    }
+
+    /// Removes the clip rectangle added by the last call to `push_clip`
+    pub fn pop_clip(&mut self) {
+        self.clip_stack.pop();
+    }
+
+    fn push_text(&mut self, mut text: TextData) {
+        text.clip = self.clip_stack.current();
+        self.texts.push(text);
+    }
+
+    /// Queues text whose first baseline starts at `(x, y)`, lines are broken at every `'\n'`, see `DrawText::queue_text`
+    pub fn queue_text(&mut self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str) {
+        let text = self.glyph_cache.queue_text(x, y, size, color, text);
+        self.push_text(text);
+    }
+
+    /// Like `queue_text` but lines are shortened to fit, see `DrawText::queue_text_truncated`
+    pub fn queue_text_truncated(&mut self, x: f32, y: f32, style: &TextStyle, max_width: f32, truncation: Truncation, text: &str) {
+        let text = self.glyph_cache.layout.with_style(style).truncate(style.size, max_width, truncation, "…", text);
+        self.queue_text_styled(x, y, style, &text);
+    }
+
+    /// Like `queue_text` but in another face, with extra spacing, with emulated bold and oblique glyphs or at a depth, see `DrawText::queue_text_styled`.
+    /// There is no depth buffer, so `TextStyle::depth` only affects the order text is drawn in.
+    pub fn queue_text_styled(&mut self, x: f32, y: f32, style: &TextStyle, text: &str) {
+        let text = self.glyph_cache.queue_text_styled(x, y, style, text);
+        self.push_text(text);
+    }
+
+    /// Like `queue_text_styled` but the laid out text is transformed, see `DrawText::queue_text_transformed`
+    pub fn queue_text_transformed(&mut self, x: f32, y: f32, style: &TextStyle, text: &str, transform: Transform) {
+        let text = self.glyph_cache.queue_text_transformed(x, y, style, text, transform);
+        self.push_text(text);
+    }
+
+    /// A `Layout` for laying out text on other threads, see `DrawText::layout`
+    pub fn layout(&self) -> Layout {
+        self.glyph_cache.layout.clone()
+    }
+
+    pub fn queue_laid_out_text(&mut self, text: &mut LaidOutText) {
+        let text = self.glyph_cache.queue_laid_out_text(text);
+        self.push_text(text);
+    }
+
+    /// Like `queue_laid_out_text` but also draws a selection and caret, see `DrawText::queue_selected_text`
+    pub fn queue_selected_text(&mut self, text: &mut LaidOutText, selection: Range<usize>, caret: Option<usize>, blink_time: f32, style: &SelectionStyle) {
+        let text = self.glyph_cache.queue_selected_text(text, selection, caret, blink_time, style);
+        self.push_text(text);
+    }
+
+    /// Like `queue_laid_out_text` but also underlines IME preedit text, see `DrawText::queue_preedit_text`
+    pub fn queue_preedit_text(&mut self, text: &mut LaidOutText, preedit: Range<usize>, cursor: Option<Range<usize>>, blink_time: f32, style: &SelectionStyle) {
+        let text = self.glyph_cache.queue_preedit_text(text, preedit, cursor, blink_time, style);
+        self.push_text(text);
+    }
+
+    /// Creates text that is drawn by every call to `draw_text` until it is removed, see `DrawText::create_text`
+    pub fn create_text(&mut self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str) -> TextHandle {
+        self.retained.create(x, y, size, color, text)
+    }
+
+    pub fn remove_text(&mut self, handle: TextHandle) {
+        self.retained.remove(handle);
+    }
+
+    pub fn set_text_content(&mut self, handle: TextHandle, text: &str) {
+        self.retained.set_content(handle, text);
+    }
+
+    pub fn set_text_position(&mut self, handle: TextHandle, x: f32, y: f32) {
+        self.retained.set_position(handle, x, y);
+    }
+
+    pub fn set_text_size(&mut self, handle: TextHandle, size: f32) {
+        self.retained.set_size(handle, size);
+    }
+
+    pub fn set_text_color(&mut self, handle: TextHandle, color: [f32; 4]) {
+        self.retained.set_color(handle, color);
+    }
+
+    /// Draws all queued text on top of `pixels`, a `width * height` RGBA8 image stored row by row.
+    pub fn draw_text(&mut self, pixels: &mut [u8]) {
+        assert_eq!(pixels.len(), self.width as usize * self.height as usize * 4, "pixels must be a width * height RGBA8 image");
+
+        self.retained.queue_glyphs(&mut self.glyph_cache);
+        self.glyph_cache.update();
+        glyph_cache::sort_by_depth(&mut self.texts);
+
+        let settings = Settings {
+            gamma:        self.gamma,
+            antialiasing: self.antialiasing,
+            blend_mode:   self.blend_mode,
+            srgb:         self.srgb,
+        };
+        let dimensions = [self.width, self.height];
+        let screen = ClipRect { min: [0, 0], max: [self.width as i32, self.height as i32] };
+
+        // retained text is drawn below queued text
+        let glyph_cache = &self.glyph_cache;
+        for retained in self.retained.iter_mut() {
+            for quad in retained.cached(glyph_cache, |text| glyph_cache.quads(text)) {
+                let mut quad = quad.clone();
+                quad.color = color::target_color(quad.color, self.color_space, self.srgb);
+                draw_quad(&glyph_cache.pixels, &quad, quad.corners(None), &settings, &screen, self.width, pixels);
+            }
+        }
+
+        for text in self.texts.drain(..) {
+            let clip = text.clip.map(|clip| clip.clamp(dimensions)).unwrap_or(screen);
+            for mut quad in self.glyph_cache.quads(&text) {
+                quad.color = color::target_color(quad.color, self.color_space, self.srgb);
+                let corners = quad.corners(text.transform.as_ref());
+                draw_quad(&self.glyph_cache.pixels, &quad, corners, &settings, &clip, self.width, pixels);
+            }
+        }
+    }
+
+    /// Draws all queued text onto a new transparent `width * height` RGBA8 image.
+    pub fn render(&mut self) -> Vec<u8> {
+        let mut pixels = vec!(0; self.width as usize * self.height as usize * 4);
+        self.draw_text(&mut pixels);
+        pixels
+    }
+}
+
+impl QueueText for CpuDrawText {
+    fn layout(&self) -> Layout {
+        CpuDrawText::layout(self)
+    }
+
+    fn queue_laid_out_text(&mut self, text: &mut LaidOutText) {
+        CpuDrawText::queue_laid_out_text(self, text);
+    }
+
+    fn queue_selected_text(&mut self, text: &mut LaidOutText, selection: Range<usize>, caret: Option<usize>, blink_time: f32, style: &SelectionStyle) {
+        CpuDrawText::queue_selected_text(self, text, selection, caret, blink_time, style);
+    }
+
+    fn queue_preedit_text(&mut self, text: &mut LaidOutText, preedit: Range<usize>, cursor: Option<Range<usize>>, blink_time: f32, style: &SelectionStyle) {
+        CpuDrawText::queue_preedit_text(self, text, preedit, cursor, blink_time, style);
+    }
+}
+
+/// The equivalent of the state baked into `DrawText`'s pipeline
+struct Settings {
+    gamma:        Gamma,
+    antialiasing: Antialiasing,
+    blend_mode:   BlendMode,
+    srgb:         bool,
+}
+
+/// `corners` are from `Quad::corners`, they may be transformed but always form a parallelogram.
+/// Only pixels within `clip` are drawn, it must lie within the image.
+fn draw_quad(cache_pixels: &[u8], quad: &Quad, corners: [[f32; 2]; 4], settings: &Settings, clip: &ClipRect, width: u32, pixels: &mut [u8]) {
+    let gamma = settings.gamma;
+
+    // the top left corner is at uv.min, the edges point towards uv.max.x and uv.max.y
+    let origin = corners[1];
+    let edge_x = [corners[2][0] - origin[0], corners[2][1] - origin[1]];
+    let edge_y = [corners[0][0] - origin[0], corners[0][1] - origin[1]];
+    let determinant = edge_x[0] * edge_y[1] - edge_x[1] * edge_y[0];
+    if determinant.abs() < f32::EPSILON {
+        return;
+    }
+
+    let min_x = corners.iter().map(|c| c[0]).fold(f32::INFINITY, f32::min).floor().max(clip.min[0] as f32) as u32;
+    let min_y = corners.iter().map(|c| c[1]).fold(f32::INFINITY, f32::min).floor().max(clip.min[1] as f32) as u32;
+    let max_x = corners.iter().map(|c| c[0]).fold(f32::NEG_INFINITY, f32::max).ceil().min(clip.max[0] as f32).max(0.0) as u32;
+    let max_y = corners.iter().map(|c| c[1]).fold(f32::NEG_INFINITY, f32::max).ceil().min(clip.max[1] as f32).max(0.0) as u32;
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            // sample at the pixel center like the rasterizer does
+            let p = [x as f32 + 0.5 - origin[0], y as f32 + 0.5 - origin[1]];
+            let s = (p[0] * edge_y[1] - p[1] * edge_y[0]) / determinant;
+            let t = (edge_x[0] * p[1] - edge_x[1] * p[0]) / determinant;
+            if !(0.0..1.0).contains(&s) || !(0.0..1.0).contains(&t) {
+                continue;
+            }
+            let u = quad.uv.min.x + (quad.uv.max.x - quad.uv.min.x) * s;
+            let v = quad.uv.min.y + (quad.uv.max.y - quad.uv.min.y) * t;
+
+            let coverage = match settings.antialiasing {
+                // solid rectangles such as selection highlights are fully covered
+                _ if quad.is_solid() => [1.0; 3],
+                Antialiasing::Grayscale => {
+                    let coverage = sample_linear(cache_pixels, u, v);
+                    [coverage, coverage, coverage]
+                }
+                Antialiasing::Subpixel(order) => {
+                    let coverage = sample_subpixels(cache_pixels, quad, u, v);
+                    match order {
+                        SubpixelOrder::Rgb => coverage,
+                        SubpixelOrder::Bgr => [coverage[2], coverage[1], coverage[0]],
+                    }
+                }
+            };
+            let alpha = [
+                quad.color[3] * gamma.adjust_coverage(coverage[0], quad.color),
+                quad.color[3] * gamma.adjust_coverage(coverage[1], quad.color),
+                quad.color[3] * gamma.adjust_coverage(coverage[2], quad.color),
+            ];
+            let average_alpha = (alpha[0] + alpha[1] + alpha[2]) / 3.0;
+
+            // fragment.glsl: f_color = vec4(v_color.rgb, v_color.a * coverage), premultiplied by the blend factors below
+            // fragment_subpixel.glsl blends each channel with its own alpha via dual source blending
+            let src = [quad.color[0], quad.color[1], quad.color[2], average_alpha];
+            let src_factor = [alpha[0], alpha[1], alpha[2], average_alpha];
+            let (src_factor, dst_factor) = match settings.blend_mode {
+                BlendMode::Alpha => (
+                    src_factor,
+                    [1.0 - alpha[0], 1.0 - alpha[1], 1.0 - alpha[2], 1.0 - average_alpha],
+                ),
+                BlendMode::Premultiplied => (
+                    [alpha[0], alpha[1], alpha[2], 1.0],
+                    [1.0 - alpha[0], 1.0 - alpha[1], 1.0 - alpha[2], 1.0 - average_alpha],
+                ),
+                BlendMode::Additive => (
+                    [alpha[0], alpha[1], alpha[2], 0.0],
+                    [1.0; 4],
+                ),
+            };
+
+            let index = (y as usize * width as usize + x as usize) * 4;
+            blend(&mut pixels[index..index+4], src, src_factor, dst_factor, settings.srgb);
+        }
+    }
+}
+
+/// Matches the fixed function blending of `DrawText`'s pipeline: src * src_factor + dst * dst_factor on every channel.
+/// On sRGB images the color channels are blended in linear space, the alpha channel is never encoded.
+fn blend(dst: &mut [u8], src: [f32; 4], src_factor: [f32; 4], dst_factor: [f32; 4], srgb: bool) {
+    for (i, dst) in dst.iter_mut().enumerate() {
+        let encoded = srgb && i < 3;
+        let mut dst_value = *dst as f32 / 255.0;
+        if encoded {
+            dst_value = color::srgb_to_linear(dst_value);
+        }
+        let mut value = src[i] * src_factor[i] + dst_value * dst_factor[i];
+        if encoded {
+            value = color::linear_to_srgb(value.max(0.0));
+        }
+        *dst = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+}
+
+/// Bilinear sample of the cache texture, matching the `Filter::Linear` sampler used by `DrawText`
+fn sample_linear(cache_pixels: &[u8], u: f32, v: f32) -> f32 {
+    let x = u * CACHE_WIDTH as f32 - 0.5;
+    let y = v * CACHE_HEIGHT as f32 - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+
+    let texel = |x: f32, y: f32| {
+        // the sampler uses SamplerAddressMode::Repeat
+        let x = (x as i64).rem_euclid(CACHE_WIDTH as i64) as usize;
+        let y = (y as i64).rem_euclid(CACHE_HEIGHT as i64) as usize;
+        cache_pixels[y * CACHE_WIDTH + x] as f32 / 255.0
+    };
+
+    let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+    let bottom = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Matches fragment_subpixel.glsl: the cache holds glyphs at 3x horizontal resolution and (u, v) is at the center of the middle subpixel.
+/// Returns the LCD filtered coverage of the left, middle and right subpixels.
+fn sample_subpixels(cache_pixels: &[u8], quad: &Quad, u: f32, v: f32) -> [f32; 3] {
+    let mut texels = [0.0; 7];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let u = u + (i as f32 - 3.0) / CACHE_WIDTH as f32;
+        let u = u.max(quad.uv_bounds.min.x).min(quad.uv_bounds.max.x);
+        let v = v.max(quad.uv_bounds.min.y).min(quad.uv_bounds.max.y);
+        *texel = sample_linear(cache_pixels, u, v);
+    }
+
+    let mut coverage = [0.0; 3];
+    for (c, coverage) in coverage.iter_mut().enumerate() {
+        *coverage = LCD_FILTER.iter().enumerate().map(|(k, weight)| weight * texels[c + k]).sum();
+    }
+    coverage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Synthesis;
+
+    const WIDTH: u32 = 32;
+    const HEIGHT: u32 = 32;
+
+    fn pixel(pixels: &[u8], x: u32, y: u32) -> [u8; 4] {
+        let index = (y * WIDTH + x) as usize * 4;
+        [pixels[index], pixels[index + 1], pixels[index + 2], pixels[index + 3]]
+    }
+
+    /// A full block covering the middle of the image
+    fn queue_block(draw_text: &mut CpuDrawText, color: [f32; 4]) {
+        draw_text.queue_text(4.0, 24.0, 32.0, color, "\u{2588}");
+    }
+
+    #[test]
+    fn nothing_queued() {
+        let mut draw_text = CpuDrawText::new(WIDTH, HEIGHT);
+        assert!(draw_text.render().iter().all(|value| *value == 0));
+    }
+
+    #[test]
+    fn glyph_coverage() {
+        let mut draw_text = CpuDrawText::new(WIDTH, HEIGHT);
+        queue_block(&mut draw_text, [1.0, 1.0, 1.0, 1.0]);
+        let pixels = draw_text.render();
+
+        assert_eq!(pixel(&pixels, 10, 16), [255, 255, 255, 255]);
+        // left of the pen position
+        assert_eq!(pixel(&pixels, 1, 16), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn alpha_blend() {
+        let mut draw_text = CpuDrawText::new(WIDTH, HEIGHT);
+        queue_block(&mut draw_text, [1.0, 0.0, 0.0, 0.5]);
+        let mut pixels: Vec<u8> = [0, 0, 255, 255].iter().cloned().cycle().take((WIDTH * HEIGHT * 4) as usize).collect();
+        draw_text.draw_text(&mut pixels);
+
+        assert_eq!(pixel(&pixels, 10, 16), [128, 0, 128, 191]);
+        assert_eq!(pixel(&pixels, 1, 16), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn additive_blend() {
+        let mut draw_text = CpuDrawText::new(WIDTH, HEIGHT);
+        draw_text.set_blend_mode(BlendMode::Additive);
+        queue_block(&mut draw_text, [0.5, 0.5, 0.5, 1.0]);
+        let mut pixels: Vec<u8> = [64, 0, 0, 255].iter().cloned().cycle().take((WIDTH * HEIGHT * 4) as usize).collect();
+        draw_text.draw_text(&mut pixels);
+
+        assert_eq!(pixel(&pixels, 10, 16), [192, 128, 128, 255]);
+    }
+
+    #[test]
+    fn bold_blends_once() {
+        let covered = |bold| {
+            let mut draw_text = CpuDrawText::new(WIDTH, HEIGHT);
+            draw_text.set_blend_mode(BlendMode::Additive);
+            let style = TextStyle { synthesis: Synthesis { bold, oblique: false }, ..TextStyle::new(32.0, [0.5, 0.5, 0.5, 1.0]) };
+            draw_text.queue_text_styled(4.0, 24.0, &style, "\u{2588}");
+            let mut pixels: Vec<u8> = [64, 0, 0, 255].iter().cloned().cycle().take((WIDTH * HEIGHT * 4) as usize).collect();
+            draw_text.draw_text(&mut pixels);
+
+            assert_eq!(pixel(&pixels, 10, 16), [192, 128, 128, 255]);
+            (0..WIDTH).filter(|x| pixel(&pixels, *x, 16)[1] > 0).count()
+        };
+        assert!(covered(true) > covered(false));
+    }
+
+    #[test]
+    fn depth_order() {
+        let mut draw_text = CpuDrawText::new(WIDTH, HEIGHT);
+        let near = TextStyle { depth: Some(0.0), ..TextStyle::new(32.0, [1.0, 0.0, 0.0, 1.0]) };
+        let far = TextStyle { depth: Some(0.5), ..TextStyle::new(32.0, [0.0, 0.0, 1.0, 1.0]) };
+        draw_text.queue_text_styled(4.0, 24.0, &near, "\u{2588}");
+        draw_text.queue_text_styled(4.0, 24.0, &far, "\u{2588}");
+        let pixels = draw_text.render();
+
+        // queued last but further away
+        assert_eq!(pixel(&pixels, 10, 16), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn nested_clip() {
+        let mut draw_text = CpuDrawText::new(WIDTH, HEIGHT);
+        draw_text.push_clip(8.0, 0.0, 16.0, 32.0);
+        draw_text.push_clip(0.0, 0.0, 12.0, 32.0);
+        queue_block(&mut draw_text, [1.0, 1.0, 1.0, 1.0]);
+        draw_text.pop_clip();
+        let pixels = draw_text.render();
+
+        // only the intersection of both rects is drawn
+        assert_eq!(pixel(&pixels, 6, 16), [0, 0, 0, 0]);
+        assert_eq!(pixel(&pixels, 10, 16), [255, 255, 255, 255]);
+        assert_eq!(pixel(&pixels, 14, 16), [0, 0, 0, 0]);
+
+        // back to the outer rect
+        queue_block(&mut draw_text, [1.0, 1.0, 1.0, 1.0]);
+        let pixels = draw_text.render();
+        assert_eq!(pixel(&pixels, 6, 16), [0, 0, 0, 0]);
+        assert_eq!(pixel(&pixels, 14, 16), [255, 255, 255, 255]);
+
+        draw_text.pop_clip();
+        queue_block(&mut draw_text, [1.0, 1.0, 1.0, 1.0]);
+        let pixels = draw_text.render();
+        assert_eq!(pixel(&pixels, 6, 16), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn retained_text() {
+        let mut draw_text = CpuDrawText::new(WIDTH, HEIGHT);
+        let handle = draw_text.create_text(4.0, 24.0, 32.0, [1.0, 1.0, 1.0, 1.0], "\u{2588}");
+        // drawn every frame without being queued again
+        assert_eq!(pixel(&draw_text.render(), 10, 16), [255, 255, 255, 255]);
+        assert_eq!(pixel(&draw_text.render(), 10, 16), [255, 255, 255, 255]);
+
+        draw_text.set_text_color(handle, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(pixel(&draw_text.render(), 10, 16), [255, 0, 0, 255]);
+
+        draw_text.set_text_position(handle, 20.0, 24.0);
+        let pixels = draw_text.render();
+        assert_eq!(pixel(&pixels, 10, 16), [0, 0, 0, 0]);
+        assert_eq!(pixel(&pixels, 26, 16), [255, 0, 0, 255]);
+
+        draw_text.remove_text(handle);
+        assert!(draw_text.render().iter().all(|value| *value == 0));
+    }
+}
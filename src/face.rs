@@ -0,0 +1,71 @@
+use rusttype::Font;
+
+use std::sync::Arc;
+
+/// A font face embedded in the library, see `TextStyle::face`.
+///
+/// DejaVu Sans is always embedded, the other faces of the DejaVu family are embedded by enabling their cargo features,
+/// or all of them with `dejavu-family`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Face {
+    #[default]
+    Sans = 0,
+    #[cfg(feature = "dejavu-sans-bold")]
+    SansBold = 1,
+    #[cfg(feature = "dejavu-sans-oblique")]
+    SansOblique = 2,
+    #[cfg(feature = "dejavu-sans-mono")]
+    SansMono = 3,
+    #[cfg(feature = "dejavu-serif")]
+    Serif = 4,
+}
+
+/// Every embedded face
+const FACES: &[Face] = &[
+    Face::Sans,
+    #[cfg(feature = "dejavu-sans-bold")]
+    Face::SansBold,
+    #[cfg(feature = "dejavu-sans-oblique")]
+    Face::SansOblique,
+    #[cfg(feature = "dejavu-sans-mono")]
+    Face::SansMono,
+    #[cfg(feature = "dejavu-serif")]
+    Face::Serif,
+];
+
+impl Face {
+    fn font_data(&self) -> &'static [u8] {
+        match self {
+            Face::Sans        => include_bytes!("DejaVuSans.ttf"),
+            #[cfg(feature = "dejavu-sans-bold")]
+            Face::SansBold    => include_bytes!("DejaVuSans-Bold.ttf"),
+            #[cfg(feature = "dejavu-sans-oblique")]
+            Face::SansOblique => include_bytes!("DejaVuSans-Oblique.ttf"),
+            #[cfg(feature = "dejavu-sans-mono")]
+            Face::SansMono    => include_bytes!("DejaVuSansMono.ttf"),
+            #[cfg(feature = "dejavu-serif")]
+            Face::Serif       => include_bytes!("DejaVuSerif.ttf"),
+        }
+    }
+}
+
+/// The embedded faces, parsed once and shared by every clone
+#[derive(Clone)]
+pub(crate) struct Fonts {
+    /// Indexed by `Face as usize`, `None` for faces that are not embedded
+    fonts: Arc<[Option<Font<'static>>; 5]>,
+}
+
+impl Fonts {
+    pub fn new() -> Fonts {
+        let mut fonts = [None, None, None, None, None];
+        for face in FACES {
+            fonts[*face as usize] = Some(Font::from_bytes(face.font_data()).unwrap());
+        }
+        Fonts { fonts: Arc::new(fonts) }
+    }
+
+    pub fn get(&self, face: Face) -> &Font<'static> {
+        self.fonts[face as usize].as_ref().unwrap()
+    }
+}
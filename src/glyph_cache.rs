@@ -0,0 +1,577 @@
+use rusttype::{Point, PositionedGlyph, Rect, Scale, point};
+use rusttype::gpu_cache::{Cache, CachedBy};
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::clip::ClipRect;
+use crate::face::Face;
+use crate::layout::{Layout, LaidOutText, Monospace, Snapping, Synthesis, TextStyle};
+use crate::selection::{self, SelectionStyle};
+use crate::tab_stops::TabStops;
+use crate::math::Matrix4;
+use crate::transform::Transform;
+
+pub(crate) const CACHE_WIDTH: usize = 1000;
+pub(crate) const CACHE_HEIGHT: usize = 1000;
+
+/// rusttype raises smaller cache tolerances to this
+const MIN_TOLERANCE: f32 = 0.001;
+
+/// Empty texels around every cached glyph, rusttype pads glyphs by one texel when `pad_glyphs` is set
+const GLYPH_PADDING: u32 = 1;
+
+/// Cache font id of the glyphs of a face, synthetic bold glyphs use the next id so they never share a cache entry with regular glyphs
+fn font_id(face: Face) -> usize {
+    face as usize * 2
+}
+
+/// Slant of synthetic oblique glyphs, horizontal pixels per pixel above the baseline, matching FreeType's
+const OBLIQUE_SHEAR: f32 = 0.2126;
+
+/// FreeType's default LCD filter, must match `lcd_filter` in fragment_subpixel.glsl
+pub(crate) const LCD_FILTER: [f32; 5] = [8.0 / 256.0, 77.0 / 256.0, 86.0 / 256.0, 77.0 / 256.0, 8.0 / 256.0];
+
+/// How glyph edges are smoothed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Antialiasing {
+    /// A single coverage value per pixel, works on any background.
+    #[default]
+    Grayscale,
+    /// Glyphs are rasterized at 3x horizontal resolution and filtered into separate coverage values for each
+    /// color channel. Gives sharper small text on LCD monitors but requires an opaque background and
+    /// a screen whose subpixels are laid out horizontally in the given order.
+    Subpixel(SubpixelOrder),
+}
+
+/// The order of the subpixels within a pixel of the screen, from left to right.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubpixelOrder {
+    Rgb,
+    Bgr,
+}
+
+#[derive(Clone)]
+pub(crate) struct TextData {
+    pub glyphs: Vec<PositionedGlyph<'static>>,
+    pub color:  [f32; 4],
+    /// Text in world space is laid out at the origin and placed by this matrix, otherwise the text is in screen space
+    pub model:  Option<Matrix4>,
+    /// Screen space text with a depth is depth tested
+    pub depth:  Option<f32>,
+    /// Applied to the quads of screen space text
+    pub transform: Option<Transform>,
+    /// Pixels outside of this rect are not drawn
+    pub clip:   Option<ClipRect>,
+    /// The glyphs in the range are drawn in the given color instead, e.g. selected text
+    pub recolor:     Option<(Range<usize>, [f32; 4])>,
+    /// Drawn below the glyphs, e.g. selection highlights
+    pub backgrounds: Vec<Solid>,
+    /// Drawn above the glyphs, e.g. carets
+    pub overlays:    Vec<Solid>,
+    pub face:        Face,
+    pub synthesis:   Synthesis,
+    /// One for every glyph when `synthesis.bold` is set, otherwise empty
+    pub bold:        Vec<BoldGlyph>,
+}
+
+/// What a synthetic bold glyph is cached as, see `bold_carrier`
+#[derive(Clone)]
+pub(crate) struct BoldGlyph {
+    carrier: PositionedGlyph<'static>,
+    width:   f32,
+}
+
+/// A solid rectangle drawn along with text, in physical pixels
+#[derive(Clone)]
+pub(crate) struct Solid {
+    pub rect:  Rect<f32>,
+    pub color: [f32; 4],
+}
+
+impl Solid {
+    /// `rect` is in logical pixels
+    pub fn new(rect: crate::layout::Rect, scale_factor: f32, color: [f32; 4]) -> Solid {
+        Solid {
+            rect: Rect {
+                min: point(rect.x * scale_factor, rect.y * scale_factor),
+                max: point((rect.x + rect.width) * scale_factor, (rect.y + rect.height) * scale_factor),
+            },
+            color,
+        }
+    }
+}
+
+impl TextData {
+    /// Text without a depth is drawn on top
+    pub fn depth(&self) -> f32 {
+        self.depth.unwrap_or(0.0)
+    }
+
+    /// The index, cache font id and synthetic bold carrier of every glyph to draw
+    fn cached_glyphs(&self) -> impl Iterator<Item = (usize, usize, &PositionedGlyph<'static>, Option<&BoldGlyph>)> + '_ {
+        let font_id = font_id(self.face);
+        self.glyphs.iter().enumerate().map(move |(i, glyph)| (i, font_id, glyph, self.bold.get(i)))
+    }
+}
+
+/// The synthetic bold carriers of laid out glyphs, computed once so they are not measured again every time the glyphs are queued
+pub(crate) fn bold_glyphs(glyphs: &[PositionedGlyph<'static>], synthesis: Synthesis) -> Vec<BoldGlyph> {
+    if !synthesis.bold {
+        return vec!();
+    }
+    glyphs.iter().map(|glyph| {
+        let width = synthesis.bold_width(glyph.unpositioned().scale());
+        BoldGlyph { carrier: bold_carrier(glyph, width), width }
+    }).collect()
+}
+
+/// An emboldened glyph in the cache texture
+#[derive(Clone, Copy)]
+struct BoldRaster {
+    /// The top left corner of the raster relative to the position of the glyph, in the units the glyph was laid out in
+    offset: Point<f32>,
+    /// In texels
+    width:  u32,
+    height: u32,
+}
+
+/// A glyph as tall as `glyph` and wide enough to hold it emboldened.
+/// The cache rasterizes this stretched glyph, only to have its space taken by `embolden(glyph)` once it is uploaded.
+fn bold_carrier(glyph: &PositionedGlyph<'static>, bold_width: f32) -> PositionedGlyph<'static> {
+    let bb = match glyph.pixel_bounding_box() {
+        Some(bb) => bb,
+        None     => return glyph.clone(),
+    };
+    let width = bb.width() + bold_width.ceil() as i32;
+    let scale = glyph.unpositioned().scale();
+    let mut stretch = (width + 1) as f32 / bb.width() as f32;
+    let mut carrier = glyph.clone();
+    // the bounds of the stretched glyph are rounded to whole texels, so stretch it further until it is wide enough
+    for _ in 0..8 {
+        carrier = glyph.unpositioned().unscaled().clone().scaled(Scale { x: scale.x * stretch, y: scale.y }).positioned(glyph.position());
+        if carrier.pixel_bounding_box().map(|bb| bb.width() >= width).unwrap_or(false) {
+            break;
+        }
+        stretch *= 1.5;
+    }
+    carrier
+}
+
+/// Rasterizes the glyph thickened `bold_width` texels to the right, every texel taking the highest coverage within that distance to its left.
+/// Returns where the raster goes and its coverage row by row.
+fn embolden(glyph: &PositionedGlyph<'static>, bold_width: f32) -> Option<(BoldRaster, Vec<u8>)> {
+    let bb = glyph.pixel_bounding_box()?;
+    let (glyph_width, height) = (bb.width() as usize, bb.height() as usize);
+    let mut coverage = vec!(0.0; glyph_width * height);
+    glyph.draw(|x, y, v| coverage[y as usize * glyph_width + x as usize] = v);
+
+    // a fractional bold width spreads partial coverage into the last texel
+    let whole = bold_width.floor() as usize;
+    let fraction = bold_width - whole as f32;
+    let width = glyph_width + bold_width.ceil() as usize;
+    let mut pixels = vec!(0; width * height);
+    for y in 0..height {
+        let row = &coverage[y * glyph_width..(y + 1) * glyph_width];
+        let left_of = |x: usize, distance: usize| x.checked_sub(distance).and_then(|x| row.get(x)).cloned().unwrap_or(0.0);
+        for x in 0..width {
+            let value = (0..=whole).map(|distance| left_of(x, distance)).fold(left_of(x, whole + 1) * fraction, f32::max);
+            pixels[y * width + x] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+
+    let offset = point(bb.min.x as f32 - glyph.position().x, bb.min.y as f32 - glyph.position().y);
+    Some((BoldRaster { offset, width: width as u32, height: height as u32 }, pixels))
+}
+
+/// The texel at texture coordinates from the cache
+fn texel(uv: Point<f32>) -> [u32; 2] {
+    [(uv.x * CACHE_WIDTH as f32).round() as u32, (uv.y * CACHE_HEIGHT as f32).round() as u32]
+}
+
+/// Orders texts furthest first so nearer text is drawn over it, texts at the same depth keep the order they were queued in
+pub(crate) fn sort_by_depth(texts: &mut [TextData]) {
+    texts.sort_by(|a, b| b.depth().partial_cmp(&a.depth()).unwrap_or(Ordering::Equal));
+}
+
+/// A glyph to be drawn, in screen pixels, along with its location in the cache texture.
+/// Samples must be clamped to `uv_bounds` to avoid reading neighbouring glyphs when filtering subpixels.
+#[derive(Clone)]
+pub(crate) struct Quad {
+    pub screen:    Rect<f32>,
+    pub uv:        Rect<f32>,
+    pub uv_bounds: Rect<f32>,
+    pub color:     [f32; 4],
+    /// The quad is slanted by moving each point right by `shear` times its height above `baseline`
+    pub shear:     f32,
+    pub baseline:  f32,
+}
+
+/// Quads with texture coordinates below -0.5 are drawn fully covered, must match fragment.glsl and fragment_subpixel.glsl.
+/// Subpixel glyphs at the edge of the cache have slightly negative texture coordinates, so the threshold leaves room for them.
+const SOLID_UV: Rect<f32> = Rect { min: Point { x: -1.0, y: -1.0 }, max: Point { x: -1.0, y: -1.0 } };
+
+impl Quad {
+    fn solid(solid: &Solid) -> Quad {
+        Quad { screen: solid.rect, uv: SOLID_UV, uv_bounds: SOLID_UV, color: solid.color, shear: 0.0, baseline: 0.0 }
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.uv.min.x < -0.5
+    }
+
+    /// The bottom left, top left, top right and bottom right corners of the quad, in the same order as the corners of `uv`
+    pub fn corners(&self, transform: Option<&Transform>) -> [[f32; 2]; 4] {
+        let mut corners = [
+            [self.screen.min.x, self.screen.max.y],
+            [self.screen.min.x, self.screen.min.y],
+            [self.screen.max.x, self.screen.min.y],
+            [self.screen.max.x, self.screen.max.y],
+        ];
+        for corner in &mut corners {
+            corner[0] += (self.baseline - corner[1]) * self.shear;
+            if let Some(transform) = transform {
+                *corner = transform.transform_point(*corner);
+            }
+        }
+        corners
+    }
+}
+
+/// Layout and glyph caching shared by the GPU and CPU renderers.
+/// The cache texture is kept in `pixels` as a single 8 bit coverage channel.
+///
+/// Quads created before `cache_generation` changed must be created again as the glyphs may have moved within the cache.
+pub(crate) struct GlyphCache {
+    pub layout:           Layout,
+    cache:                Cache<'static>,
+    pub cache_generation: u64,
+    pub pixels:           Vec<u8>,
+    /// Synthetic bold glyphs queued since the last `update`, with their cache font id and bold width
+    bold_queue:           Vec<(usize, PositionedGlyph<'static>, BoldGlyph)>,
+    /// The emboldened glyphs in the cache texture by the texel at their top left corner
+    bold_rasters:         HashMap<[u32; 2], BoldRaster>,
+}
+
+impl GlyphCache {
+    pub fn new() -> GlyphCache {
+        // the padding is relied on to find uploaded bold carriers and to sample past the edges of glyphs
+        let cache = Cache::builder().dimensions(CACHE_WIDTH as u32, CACHE_HEIGHT as u32).pad_glyphs(true).build();
+        let pixels = vec!(0; CACHE_WIDTH * CACHE_HEIGHT);
+
+        GlyphCache { layout: Layout::new(), cache, cache_generation: 0, pixels, bold_queue: vec!(), bold_rasters: HashMap::new() }
+    }
+
+    /// Text laid out before this changed must be laid out again
+    pub fn layout_generation(&self) -> u64 {
+        self.layout.generation
+    }
+
+    /// Subpixel glyphs are rasterized differently, so all cached glyphs are thrown out when this changes
+    pub fn set_subpixel(&mut self, subpixel: bool) {
+        if subpixel != self.layout.subpixel {
+            self.layout.subpixel = subpixel;
+            self.layout.generation += 1;
+            self.cache.clear();
+            self.bold_rasters.clear();
+            self.cache_generation += 1;
+        }
+    }
+
+    pub fn scale_factor(&self) -> f32 {
+        self.layout.scale_factor
+    }
+
+    /// Glyphs of a different size are separate cache entries, so cached glyphs are left to be evicted as needed
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        if scale_factor != self.layout.scale_factor {
+            self.layout.scale_factor = scale_factor;
+            self.layout.generation += 1;
+        }
+    }
+
+    pub fn snapping(&self) -> Snapping {
+        self.layout.snapping
+    }
+
+    pub fn set_snapping(&mut self, snapping: Snapping) {
+        if snapping != self.layout.snapping {
+            self.layout.snapping = snapping;
+            self.layout.generation += 1;
+        }
+    }
+
+    pub fn monospace(&self) -> Monospace {
+        self.layout.monospace
+    }
+
+    pub fn set_monospace(&mut self, monospace: Monospace) {
+        if monospace != self.layout.monospace {
+            self.layout.monospace = monospace;
+            self.layout.generation += 1;
+        }
+    }
+
+    pub fn tab_stops(&self) -> &TabStops {
+        &self.layout.tab_stops
+    }
+
+    pub fn set_tab_stops(&mut self, tab_stops: TabStops) {
+        if tab_stops != self.layout.tab_stops {
+            self.layout.tab_stops = tab_stops;
+            self.layout.generation += 1;
+        }
+    }
+
+    /// The scale and position tolerances of the cache, in pixels
+    pub fn tolerances(&self) -> (f32, f32) {
+        (self.cache.scale_tolerance(), self.cache.position_tolerance())
+    }
+
+    /// Glyphs within these tolerances of a cached glyph reuse it, all cached glyphs are thrown out when they change.
+    /// rusttype raises values below 0.001 to 0.001 and panics on negative values.
+    pub fn set_tolerances(&mut self, scale_tolerance: f32, position_tolerance: f32) {
+        // compared as rusttype stores them so smaller values do not rebuild the cache every call
+        let scale_tolerance = scale_tolerance.max(MIN_TOLERANCE);
+        let position_tolerance = position_tolerance.max(MIN_TOLERANCE);
+        if (scale_tolerance, position_tolerance) != self.tolerances() {
+            self.cache.to_builder()
+                .scale_tolerance(scale_tolerance)
+                .position_tolerance(position_tolerance)
+                .rebuild(&mut self.cache);
+            self.bold_rasters.clear();
+            self.cache_generation += 1;
+        }
+    }
+
+    /// Lays out the text and queues its glyphs for the next call to `update`
+    pub fn queue_text(&mut self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str) -> TextData {
+        let text = self.layout.layout(x, y, size, color, text);
+        self.queue_glyphs(&text);
+        text
+    }
+
+    pub fn queue_text_styled(&mut self, x: f32, y: f32, style: &TextStyle, text: &str) -> TextData {
+        let text = self.layout.with_style(style).layout(x, y, style.size, style.color, text);
+        self.queue_glyphs(&text);
+        text
+    }
+
+    /// Lays out the text with glyphs rasterized at a resolution suited to the scale of the transform, so they stay crisp once transformed
+    pub fn queue_text_transformed(&mut self, x: f32, y: f32, style: &TextStyle, text: &str, transform: Transform) -> TextData {
+        let text = self.layout.with_style(style).layout_transformed(x, y, style.size, style.color, text, transform);
+        self.queue_glyphs(&text);
+        text
+    }
+
+    /// Queues laid out text along with its selection and caret
+    pub fn queue_selected_text(&mut self, text: &mut LaidOutText, selection: Range<usize>, caret: Option<usize>, blink_time: f32, style: &SelectionStyle) -> TextData {
+        text.update(&self.layout);
+        let text = selection::selected_text(self.layout.scale_factor, text, selection, caret, blink_time, style);
+        self.queue_glyphs(&text);
+        text
+    }
+
+    pub fn queue_preedit_text(&mut self, text: &mut LaidOutText, preedit: Range<usize>, cursor: Option<Range<usize>>, blink_time: f32, style: &SelectionStyle) -> TextData {
+        text.update(&self.layout);
+        let text = selection::preedit_text(self.layout.scale_factor, text, preedit, cursor, blink_time, style);
+        self.queue_glyphs(&text);
+        text
+    }
+
+    /// Queues text laid out by a `Layout`, which is laid out again if the settings changed since
+    pub fn queue_laid_out_text(&mut self, text: &mut LaidOutText) -> TextData {
+        text.update(&self.layout);
+        let text = text.data.clone();
+        self.queue_glyphs(&text);
+        text
+    }
+
+    /// Queues the glyphs of text laid out earlier so they stay in the cache
+    pub fn queue_glyphs(&mut self, text: &TextData) {
+        for (_, font_id, glyph, bold) in text.cached_glyphs() {
+            match bold {
+                Some(bold) => {
+                    self.cache.queue_glyph(font_id + 1, bold.carrier.clone());
+                    self.bold_queue.push((font_id, glyph.clone(), bold.clone()));
+                }
+                None => self.cache.queue_glyph(font_id, glyph.clone()),
+            }
+        }
+    }
+
+    /// Rasterizes all queued glyphs into `pixels`
+    pub fn update(&mut self) {
+        let pixels = &mut self.pixels;
+        // the top left texel of every glyph uploaded, within its padding
+        let mut uploaded = HashSet::new();
+        let cached_by = self.cache.cache_queued(
+            |rect, src_data| {
+                let width = (rect.max.x - rect.min.x) as usize;
+                let height = (rect.max.y - rect.min.y) as usize;
+                let mut dst_index = rect.min.y as usize * CACHE_WIDTH + rect.min.x as usize;
+                let mut src_index = 0;
+
+                for _ in 0..height {
+                    let dst_slice = &mut pixels[dst_index..dst_index+width];
+                    let src_slice = &src_data[src_index..src_index+width];
+                    dst_slice.copy_from_slice(src_slice);
+
+                    dst_index += CACHE_WIDTH;
+                    src_index += width;
+                }
+                uploaded.insert([rect.min.x + GLYPH_PADDING, rect.min.y + GLYPH_PADDING]);
+            }
+        ).unwrap();
+
+        for (font_id, glyph, bold) in std::mem::take(&mut self.bold_queue) {
+            if let Ok(Some((uv_rect, _))) = self.cache.rect_for(font_id + 1, &bold.carrier) {
+                let min = texel(uv_rect.min);
+                if uploaded.remove(&min) {
+                    self.write_bold(min, texel(uv_rect.max), &glyph, bold.width);
+                }
+            }
+        }
+
+        // glyphs queued this frame are never evicted, but they are all moved when the cache had to be reordered to fit them
+        if let CachedBy::Reordering = cached_by {
+            self.cache_generation += 1;
+        }
+    }
+
+    /// Replaces the stretched carrier rasterized between the texels `min` and `max` with the emboldened glyph
+    fn write_bold(&mut self, min: [u32; 2], max: [u32; 2], glyph: &PositionedGlyph<'static>, bold_width: f32) {
+        let (raster, coverage) = match embolden(glyph, bold_width) {
+            Some(emboldened) => emboldened,
+            None             => return,
+        };
+        let stride = raster.width;
+        let raster = BoldRaster { width: raster.width.min(max[0] - min[0]), height: raster.height.min(max[1] - min[1]), .. raster };
+        for y in min[1]..max[1] {
+            for x in min[0]..max[0] {
+                let (raster_x, raster_y) = (x - min[0], y - min[1]);
+                let value = if raster_x < raster.width && raster_y < raster.height {
+                    coverage[(raster_y * stride + raster_x) as usize]
+                }
+                else {
+                    0
+                };
+                self.pixels[y as usize * CACHE_WIDTH + x as usize] = value;
+            }
+        }
+        self.bold_rasters.insert(min, raster);
+    }
+
+    /// The texture and screen rects of an emboldened glyph, like `Cache::rect_for` for regular glyphs
+    fn bold_rect(&self, font_id: usize, glyph: &PositionedGlyph<'static>, bold: &BoldGlyph) -> Option<(Rect<f32>, Rect<i32>)> {
+        let (uv_rect, _) = self.cache.rect_for(font_id + 1, &bold.carrier).ok()??;
+        let raster = self.bold_rasters.get(&texel(uv_rect.min))?;
+        let position = glyph.position();
+        let min = point((position.x + raster.offset.x).round() as i32, (position.y + raster.offset.y).round() as i32);
+        let screen_rect = Rect { min, max: point(min.x + raster.width as i32, min.y + raster.height as i32) };
+        let uv_rect = Rect {
+            min: uv_rect.min,
+            max: point(uv_rect.min.x + raster.width as f32 / CACHE_WIDTH as f32, uv_rect.min.y + raster.height as f32 / CACHE_HEIGHT as f32),
+        };
+        Some((uv_rect, screen_rect))
+    }
+
+    /// Must be called after `update` so that every glyph of the text is in the cache.
+    /// The backgrounds come first and the overlays last, so drawing the quads in order layers them correctly.
+    pub fn quads(&self, text: &TextData) -> Vec<Quad> {
+        let texel_width = 1.0 / CACHE_WIDTH as f32;
+        let texel_height = 1.0 / CACHE_HEIGHT as f32;
+        let backgrounds = text.backgrounds.iter().map(Quad::solid);
+        let overlays = text.overlays.iter().map(Quad::solid);
+        let shear = if text.synthesis.oblique { OBLIQUE_SHEAR } else { 0.0 };
+        let glyphs = text.cached_glyphs().filter_map(|(i, font_id, g, bold)| {
+            let rects = match bold {
+                Some(bold) => self.bold_rect(font_id, g, bold),
+                None       => self.cache.rect_for(font_id, g).ok().flatten(),
+            };
+            if let Some((uv_rect, screen_rect)) = rects {
+                // the cached glyphs are surrounded by a 1 texel border of empty padding, sampling the center of the border is safe
+                let uv_bounds = Rect {
+                    min: point(uv_rect.min.x - texel_width * 0.5, uv_rect.min.y - texel_height * 0.5),
+                    max: point(uv_rect.max.x + texel_width * 0.5, uv_rect.max.y + texel_height * 0.5),
+                };
+
+                let (screen, uv) = if self.layout.subpixel {
+                    // Cover every pixel containing a subpixel of the glyph and one more on either side for the LCD filter to spread into.
+                    // Each pixel spans 3 texels, so the uv rect is stretched to match.
+                    let min_x = (screen_rect.min.x as f32 / 3.0).floor() - 1.0;
+                    let max_x = (screen_rect.max.x as f32 / 3.0).ceil() + 1.0;
+                    let screen = Rect {
+                        min: point(min_x, screen_rect.min.y as f32),
+                        max: point(max_x, screen_rect.max.y as f32),
+                    };
+                    let uv = Rect {
+                        min: point(uv_rect.min.x + (min_x * 3.0 - screen_rect.min.x as f32) * texel_width, uv_rect.min.y),
+                        max: point(uv_rect.max.x + (max_x * 3.0 - screen_rect.max.x as f32) * texel_width, uv_rect.max.y),
+                    };
+                    (screen, uv)
+                }
+                else {
+                    let screen = Rect {
+                        min: point(screen_rect.min.x as f32, screen_rect.min.y as f32),
+                        max: point(screen_rect.max.x as f32, screen_rect.max.y as f32),
+                    };
+                    (screen, uv_rect)
+                };
+
+                let color = match &text.recolor {
+                    Some((range, color)) if range.contains(&i) => *color,
+                    _                                          => text.color,
+                };
+
+                Some(Quad {
+                    screen,
+                    uv,
+                    uv_bounds,
+                    color,
+                    shear,
+                    baseline: g.position().y,
+                })
+            }
+            else {
+                None
+            }
+        });
+        backgrounds.chain(glyphs).chain(overlays).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tolerances_below_minimum() {
+        let mut glyph_cache = GlyphCache::new();
+        glyph_cache.set_tolerances(0.0, 0.0);
+        let generation = glyph_cache.cache_generation;
+        glyph_cache.set_tolerances(0.0, 0.0005);
+        assert_eq!(glyph_cache.cache_generation, generation);
+        assert_eq!(glyph_cache.tolerances(), (MIN_TOLERANCE, MIN_TOLERANCE));
+    }
+
+    #[test]
+    fn bold_rasters_cleared_with_cache() {
+        let mut glyph_cache = GlyphCache::new();
+        let style = TextStyle { synthesis: Synthesis { bold: true, oblique: false }, ..TextStyle::new(16.0, [1.0; 4]) };
+        let text = glyph_cache.queue_text_styled(0.0, 20.0, &style, "bold");
+        glyph_cache.update();
+        assert_eq!(glyph_cache.bold_rasters.len(), 4);
+        assert_eq!(glyph_cache.quads(&text).len(), 4);
+
+        glyph_cache.set_tolerances(0.2, 0.2);
+        assert!(glyph_cache.bold_rasters.is_empty());
+        glyph_cache.set_subpixel(true);
+        assert!(glyph_cache.bold_rasters.is_empty());
+
+        let text = glyph_cache.queue_text_styled(0.0, 20.0, &style, "bold");
+        glyph_cache.update();
+        assert_eq!(glyph_cache.quads(&text).len(), 4);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::math::{self, Matrix4};
+
+/// Options for `DrawText::queue_label`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LabelOptions {
+    /// Offset in logical pixels from the projected anchor to the center of the label's baseline, e.g. `[0.0, -20.0]` to float above a unit.
+    pub offset: [f32; 2],
+    /// The distances from the camera at which the label starts and finishes fading out.
+    /// Distance is measured along the view direction, so this only has an effect with a perspective projection.
+    pub fade: Option<(f32, f32)>,
+}
+
+impl Default for LabelOptions {
+    fn default() -> LabelOptions {
+        LabelOptions {
+            offset: [0.0, 0.0],
+            fade:   None,
+        }
+    }
+}
+
+impl LabelOptions {
+    /// The opacity of the label at the given distance from the camera
+    pub(crate) fn opacity(&self, distance: f32) -> f32 {
+        match self.fade {
+            Some((start, end)) if distance > start => ((end - distance) / (end - start)).clamp(0.0, 1.0),
+            _ => 1.0,
+        }
+    }
+}
+
+/// The screen position of a projected world position
+pub(crate) struct Projected {
+    pub x:        f32,
+    pub y:        f32,
+    pub depth:    f32,
+    pub distance: f32,
+}
+
+/// Returns None when the position is behind the camera
+pub(crate) fn project(view_projection: &Matrix4, dimensions: [u32; 2], position: [f32; 3]) -> Option<Projected> {
+    let clip = math::transform(view_projection, [position[0], position[1], position[2], 1.0]);
+    if clip[3] <= 0.0 {
+        return None;
+    }
+
+    Some(Projected {
+        x:        (clip[0] / clip[3] + 1.0) / 2.0 * dimensions[0] as f32,
+        y:        (clip[1] / clip[3] + 1.0) / 2.0 * dimensions[1] as f32,
+        depth:    clip[2] / clip[3],
+        distance: clip[3],
+    })
+}
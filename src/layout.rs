@@ -0,0 +1,996 @@
+use rusttype::{Font, GlyphId, Point, PositionedGlyph, Scale, point};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::face::{Face, Fonts};
+use crate::glyph_cache::{self, TextData};
+use crate::tab_stops::{TabAlignment, TabStops};
+use crate::transform::Transform;
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Whether glyphs are moved to whole pixels, trading accurate spacing for crisp, stable edges.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Snapping {
+    /// Glyphs are placed at fractional positions, smooth but text moving by fractions of a pixel shimmers.
+    #[default]
+    None,
+    /// The start of the text is rounded to a whole pixel so moving text keeps its shape, glyphs within the text keep their fractional positions.
+    Origin,
+    /// Every glyph is rounded to a whole pixel, for pixel art UIs.
+    Glyphs,
+}
+
+/// Whether glyphs advance by a fixed width, so numbers that change often such as scores and timers keep their width.
+/// Glyphs with a fixed advance are centered in it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Monospace {
+    /// Glyphs advance by their own width and are kerned.
+    #[default]
+    None,
+    /// The digits 0 to 9 advance by the width of the widest digit.
+    Digits,
+    /// Every glyph advances by this many times the text size, e.g. 0.6, and nothing is kerned.
+    All(f32),
+}
+
+/// Which part of text too wide to fit is cut and replaced with an ellipsis, see `Layout::truncate`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Truncation {
+    /// "…of the Ancients"
+    Start,
+    /// "Sword…Ancients"
+    Middle,
+    /// "Sword of the…"
+    End,
+}
+
+/// Options for `Layout::layout_wrapped_text`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WrapOptions {
+    /// Lines wider than this many logical pixels are broken after the whitespace before the word that does not fit,
+    /// or between grapheme clusters when a single word does not fit.
+    pub max_width: Option<f32>,
+    /// Lines after this many are not laid out, see `LaidOutText::overflowed`. At least one line is always laid out.
+    pub max_lines: Option<usize>,
+}
+
+/// A distance in logical pixels or relative to the text size
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length {
+    Pixels(f32),
+    /// Multiples of the text size
+    Em(f32),
+}
+
+/// Extra space added between glyphs of laid out text, negative to tighten it, see `TextStyle::spacing`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spacing {
+    /// Added between every pair of neighbouring grapheme clusters, also known as tracking
+    pub letter: Length,
+    /// Added after every space
+    pub word:   Length,
+}
+
+impl Default for Spacing {
+    fn default() -> Spacing {
+        Spacing {
+            letter: Length::Pixels(0.0),
+            word:   Length::Pixels(0.0),
+        }
+    }
+}
+
+/// Bold and oblique faces emulated from the regular face, see `TextStyle::synthesis`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Synthesis {
+    /// Glyphs are rasterized thickened to the right and advance further to make room
+    pub bold:    bool,
+    /// Glyphs are slanted to the right around their baseline.
+    /// Only the drawn glyphs are slanted, `LaidOutText::hit_test`, `caret_rect` and `selection_rects` keep the upright glyph boxes.
+    pub oblique: bool,
+}
+
+/// How text is drawn, see `DrawText::queue_text_styled`.
+///
+/// Create one with `TextStyle::new` and change the fields that differ from plain text, e.g.
+/// `TextStyle { face: Face::SansMono, ..TextStyle::new(16.0, color) }`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextStyle {
+    /// In logical pixels
+    pub size:      f32,
+    pub color:     [f32; 4],
+    /// An embedded face such as `Face::SansMono` with the `dejavu-sans-mono` feature
+    pub face:      Face,
+    pub spacing:   Spacing,
+    pub synthesis: Synthesis,
+    /// Between 0.0 (near) and 1.0 (far), matching the depth buffer.
+    ///
+    /// Text is drawn from furthest to nearest, so text with a smaller depth is drawn over text with a larger depth regardless of the order it was queued in.
+    /// Text without a depth is treated as being at depth 0.0.
+    /// If `DrawText` was created with `new_with_depth` the text is also depth tested, so it can be hidden behind sprites that wrote to the depth buffer.
+    pub depth:     Option<f32>,
+}
+
+impl TextStyle {
+    /// Text drawn like `DrawText::queue_text` draws it, in DejaVu Sans without extra spacing, synthesis or depth
+    pub fn new(size: f32, color: [f32; 4]) -> TextStyle {
+        TextStyle {
+            size,
+            color,
+            face:      Face::Sans,
+            spacing:   Spacing::default(),
+            synthesis: Synthesis::default(),
+            depth:     None,
+        }
+    }
+}
+
+impl Synthesis {
+    /// How much wider bold glyphs are, in the units of `scale`, matching FreeType's emboldening
+    pub(crate) fn bold_width(&self, scale: Scale) -> f32 {
+        if self.bold { scale.x / 24.0 } else { 0.0 }
+    }
+}
+
+impl Length {
+    /// The length in the units of `scale`, `unit` of them per logical pixel
+    fn scaled(&self, scale: Scale, unit: f32) -> f32 {
+        match *self {
+            Length::Pixels(pixels) => pixels * unit,
+            Length::Em(em)         => em * scale.x,
+        }
+    }
+}
+
+/// Lays out text with the settings of the `DrawText` it was created by, see `DrawText::layout`.
+///
+/// A `Layout` is `Send` and cheap to clone, so text can be laid out on worker threads
+/// and the resulting `LaidOutText` sent back to be queued with `DrawText::queue_laid_out_text`.
+///
+/// Positions and sizes are given in logical pixels and laid out in physical pixels, `scale_factor` physical pixels per logical pixel.
+/// Lines are broken at every `'\n'`, and when wrapping where they would be too wide.
+#[derive(Clone)]
+pub struct Layout {
+    fonts:                   Fonts,
+    pub(crate) subpixel:     bool,
+    pub(crate) scale_factor: f32,
+    pub(crate) snapping:     Snapping,
+    pub(crate) tab_stops:    TabStops,
+    pub(crate) monospace:    Monospace,
+    // not settings of `DrawText`, but of each text
+    pub(crate) face:         Face,
+    pub(crate) spacing:      Spacing,
+    pub(crate) synthesis:    Synthesis,
+    pub(crate) depth:        Option<f32>,
+    /// Changes whenever the settings do, text laid out with other settings has to be laid out again
+    pub(crate) generation:   u64,
+}
+
+/// Text laid out by a `Layout`, ready to be queued with `DrawText::queue_laid_out_text`.
+pub struct LaidOutText {
+    pub(crate) data:       TextData,
+    pub(crate) generation: u64,
+    lines:                 Vec<Line>,
+    end:                   usize,
+    /// How far a selected line break extends the selection past the end of its line
+    line_break_width:      f32,
+    // kept to lay the text out again if the settings changed since
+    wrap_options:          WrapOptions,
+    style:                 TextStyle,
+    x:                     f32,
+    y:                     f32,
+    text:                  String,
+}
+
+/// A line of laid out text in logical pixels
+struct Line {
+    top:        f32,
+    bottom:     f32,
+    /// Every grapheme boundary in the line, from the start of the line to its end
+    carets:     Vec<Caret>,
+    /// Whether the line ends in a line break rather than being wrapped or ending the text
+    line_break: bool,
+}
+
+/// A grapheme boundary, the boundary at the end of a line has the index of the line break or the number of graphemes in the text
+#[derive(Clone, Copy)]
+struct Caret {
+    grapheme: usize,
+    /// The index of the first glyph after the boundary
+    glyph:    usize,
+    x:        f32,
+}
+
+/// Where a point is within `LaidOutText`, see `LaidOutText::hit_test`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    /// The index of the grapheme cluster under the point, or of the nearest one when the point is outside of the text.
+    /// Line breaks are grapheme clusters too, past the end of a line the index is that of the last grapheme before its line break.
+    pub grapheme: usize,
+    /// Whether the point is on the trailing half of the grapheme cluster, so a caret placed at the point goes after it
+    pub trailing: bool,
+    /// Whether the point is within the height of a line and between its start and end
+    pub inside:   bool,
+}
+
+/// A rectangle in logical pixels, `(x, y)` is its top left corner
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x:      f32,
+    pub y:      f32,
+    pub width:  f32,
+    pub height: f32,
+}
+
+impl Hit {
+    /// The index of the grapheme cluster boundary nearest to the point, where a caret placed at the point goes
+    pub fn caret(&self) -> usize {
+        self.grapheme + self.trailing as usize
+    }
+}
+
+impl Layout {
+    pub(crate) fn new() -> Layout {
+        Layout {
+            fonts:        Fonts::new(),
+            subpixel:     false,
+            scale_factor: 1.0,
+            snapping:     Snapping::default(),
+            tab_stops:    TabStops::default(),
+            monospace:    Monospace::default(),
+            face:         Face::Sans,
+            spacing:      Spacing::default(),
+            synthesis:    Synthesis::default(),
+            depth:        None,
+            generation:   0,
+        }
+    }
+
+    /// A `Layout` with the same settings that lays out text in the face, spacing, synthesis and depth of `style`,
+    /// e.g. to measure text queued with `DrawText::queue_text_styled`. The size and color are still passed to each call.
+    pub fn with_style(&self, style: &TextStyle) -> Layout {
+        Layout { face: style.face, spacing: style.spacing, synthesis: style.synthesis, depth: style.depth, .. self.clone() }
+    }
+
+    /// Lays out text like `DrawText::queue_text_styled` does, wrapped as set by `options`
+    pub fn layout_styled_text(&self, x: f32, y: f32, style: &TextStyle, text: &str, options: &WrapOptions) -> LaidOutText {
+        self.with_style(style).layout_wrapped_text(x, y, style.size, style.color, text, options)
+    }
+
+    /// The font of the face text is laid out in
+    fn font(&self) -> &Font<'static> {
+        self.fonts.get(self.face)
+    }
+
+    /// Lays out text the same way `DrawText::queue_text` does
+    pub fn layout_text(&self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str) -> LaidOutText {
+        self.layout_wrapped_text(x, y, size, color, text, &WrapOptions::default())
+    }
+
+    /// Lays out text wrapped to fit a width and limited to a number of lines, e.g. for a paragraph in a fixed size box.
+    /// Whether lines were cut off is reported by `LaidOutText::overflowed`.
+    pub fn layout_wrapped_text(&self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str, options: &WrapOptions) -> LaidOutText {
+        let mut line_ranges = vec!();
+        for line in line_ranges_of(text) {
+            match options.max_width {
+                Some(max_width) => line_ranges.extend(self.wrap(size, max_width, &text[line.clone()]).into_iter().map(|range| line.start + range.start..line.start + range.end)),
+                None            => line_ranges.push(line),
+            }
+        }
+        // the first line that does not fit starts where layout stops
+        let max_lines = options.max_lines.unwrap_or(usize::MAX).max(1);
+        let end = line_ranges.get(max_lines).map(|line| line.start).unwrap_or(text.len());
+        line_ranges.truncate(max_lines);
+
+        let data = self.layout_lines(x, y, size, color, text, &line_ranges);
+        let line_break_width = self.font().glyph(' ').scaled(Scale::uniform(size)).h_metrics().advance_width;
+        LaidOutText {
+            lines:        self.lines(&data, x, y, size, text, &line_ranges),
+            end,
+            line_break_width,
+            data,
+            generation:   self.generation,
+            wrap_options: *options,
+            style:        TextStyle { size, color, face: self.face, spacing: self.spacing, synthesis: self.synthesis, depth: self.depth },
+            x,
+            y,
+            text:         text.to_string(),
+        }
+    }
+
+    /// The distance in logical pixels from the start of the text to the end of the last glyph's advance on its longest line
+    pub fn text_width(&self, size: f32, text: &str) -> f32 {
+        let scale = self.line_scale(Scale::uniform(size), 1.0);
+        text.split('\n').map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            self.layout_line(line, &scale, point(0.0, 0.0)).1
+        }).fold(0.0, f32::max)
+    }
+
+    /// Shortens every line of `text` wider than `max_width` logical pixels at `size` by cutting grapheme clusters as chosen by `truncation`
+    /// and putting `suffix`, usually `"…"`, in their place so that the line fits. A line where not even `suffix` fits becomes just `suffix`.
+    pub fn truncate<'a>(&self, size: f32, max_width: f32, truncation: Truncation, suffix: &str, text: &'a str) -> Cow<'a, str> {
+        if self.text_width(size, text) <= max_width {
+            return Cow::Borrowed(text);
+        }
+
+        let lines: Vec<String> = text.split('\n').map(|line| {
+            let (line, line_break) = match line.strip_suffix('\r') {
+                Some(line) => (line, "\r"),
+                None       => (line, ""),
+            };
+            if self.text_width(size, line) <= max_width {
+                return format!("{}{}", line, line_break);
+            }
+
+            let graphemes: Vec<&str> = line.graphemes(true).collect();
+            let truncated = |kept: usize| {
+                let (start, end) = match truncation {
+                    Truncation::Start  => (0, kept),
+                    Truncation::Middle => (kept.div_ceil(2), kept / 2),
+                    Truncation::End    => (kept, 0),
+                };
+                let mut truncated = graphemes[..start].concat();
+                truncated.push_str(suffix);
+                truncated.push_str(&graphemes[graphemes.len() - end..].concat());
+                truncated
+            };
+
+            // binary search for the most grapheme clusters that fit, the line itself did not fit so at most all but one
+            let (mut fits, mut too_wide) = (0, graphemes.len());
+            while too_wide - fits > 1 {
+                let kept = (fits + too_wide) / 2;
+                if self.text_width(size, &truncated(kept)) <= max_width {
+                    fits = kept;
+                }
+                else {
+                    too_wide = kept;
+                }
+            }
+            format!("{}{}", truncated(fits), line_break)
+        }).collect();
+        Cow::Owned(lines.join("\n"))
+    }
+
+    pub(crate) fn layout(&self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str) -> TextData {
+        self.layout_lines(x, y, size, color, text, &line_ranges_of(text))
+    }
+
+    fn layout_lines(&self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str, lines: &[Range<usize>]) -> TextData {
+        let scale = self.scale_factor;
+        let placement = Placement { x: x * scale, y: y * scale, size: [size * scale, size * scale], unit: scale };
+        self.layout_scaled(&placement, color, text, lines)
+    }
+
+    /// Breaks a line without line breaks into the byte ranges of lines no wider than `max_width` logical pixels.
+    /// Whitespace at a break stays at the end of the line before it, where it may go past `max_width`.
+    fn wrap(&self, size: f32, max_width: f32, line: &str) -> Vec<Range<usize>> {
+        // every char is laid out as one glyph
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        let line_scale = self.line_scale(Scale::uniform(size), 1.0);
+        let (glyphs, _) = self.layout_line(line, &line_scale, point(0.0, 0.0));
+        let cell = |i: usize| self.glyph_cell(chars[i].1, &glyphs[i], &line_scale);
+        let boundaries: Vec<usize> = line.grapheme_indices(true).map(|(i, _)| i).collect();
+
+        let mut ranges = vec!();
+        let mut start = 0;
+        // the start of the last word, where the line can be broken
+        let mut word_start = 0;
+        for i in 0..chars.len() {
+            if chars[i].1.is_whitespace() {
+                continue;
+            }
+            if i > 0 && chars[i - 1].1.is_whitespace() {
+                word_start = i;
+            }
+
+            // the line starting after a break may still be too wide for the glyph, e.g. when the break was before a long word
+            let (left, advance) = cell(i);
+            while left + advance - cell(start).0 > max_width && i > start {
+                let end = if word_start > start {
+                    word_start
+                }
+                else {
+                    // the word alone does not fit, break it before the grapheme cluster that does not fit
+                    match (start + 1..=i).rev().find(|&j| boundaries.binary_search(&chars[j].0).is_ok()) {
+                        Some(end) => end,
+                        None      => break,
+                    }
+                };
+                ranges.push(chars[start].0..chars[end].0);
+                start = end;
+            }
+        }
+        ranges.push(chars.get(start).map(|(i, _)| *i).unwrap_or(0)..line.len());
+        ranges
+    }
+
+    /// Lays out the text with glyphs rasterized at a resolution suited to the scale of the transform, so they stay crisp once transformed
+    pub(crate) fn layout_transformed(&self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str, transform: Transform) -> TextData {
+        // the transform is in logical pixels, convert it to act on physical pixels
+        let scale = self.scale_factor;
+        let transform = Transform::scale(1.0 / scale, 1.0 / scale).then(&transform).then(&Transform::scale(scale, scale));
+
+        let [scale_x, scale_y] = transform.axis_scales();
+        let placement = Placement {
+            x:    x * scale * scale_x,
+            y:    y * scale * scale_y,
+            size: [size * scale * scale_x, size * scale * scale_y],
+            unit: scale * scale_x,
+        };
+        let mut text = self.layout_scaled(&placement, color, text, &line_ranges_of(text));
+        text.transform = Some(Transform::scale(1.0 / scale_x, 1.0 / scale_y).then(&transform));
+        text
+    }
+
+    /// Lays out the lines of text in physical pixels as placed by `placement`
+    fn layout_scaled(&self, placement: &Placement, color: [f32; 4], text: &str, lines: &[Range<usize>]) -> TextData {
+        let Placement { x, y, size, unit } = *placement;
+        let (x, y) = match self.snapping {
+            Snapping::None => (x, y),
+            _              => (x.round(), y.round()),
+        };
+
+        // subpixel glyphs are laid out and rasterized at 3x horizontal resolution
+        let (scale, origin, pixel_width) = if self.subpixel {
+            (Scale { x: size[0] * 3.0, y: size[1] }, point(x * 3.0, y), 3.0)
+        }
+        else {
+            (Scale { x: size[0], y: size[1] }, point(x, y), 1.0)
+        };
+
+        let v_metrics = self.font().v_metrics(scale);
+        let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+
+        let line_scale = self.line_scale(scale, unit * pixel_width);
+        let mut glyphs: Vec<PositionedGlyph<'static>> = vec!();
+        for (i, line) in lines.iter().enumerate() {
+            let origin = point(origin.x, origin.y + line_height * i as f32);
+            glyphs.extend(self.layout_line(&text[line.clone()], &line_scale, origin).0);
+        }
+
+        if self.snapping == Snapping::Glyphs {
+            for glyph in &mut glyphs {
+                let position = glyph.position();
+                glyph.set_position(point((position.x / pixel_width).round() * pixel_width, position.y.round()));
+            }
+        }
+        let bold = glyph_cache::bold_glyphs(&glyphs, self.synthesis);
+        TextData {
+            glyphs,
+            color,
+            model:       None,
+            depth:       self.depth,
+            transform:   None,
+            clip:        None,
+            recolor:     None,
+            backgrounds: vec!(),
+            overlays:    vec!(),
+            face:        self.face,
+            synthesis:   self.synthesis,
+            bold,
+        }
+    }
+
+    /// Lays out a line as one glyph per char, moving the text after every tab to the next tab stop, and returns the glyphs and the width of the line
+    fn layout_line(&self, line: &str, line_scale: &LineScale, origin: Point<f32>) -> (Vec<PositionedGlyph<'static>>, f32) {
+        let unit = line_scale.unit;
+        let mut glyphs = vec!();
+        // relative to the origin
+        let mut pen = 0.0;
+        for (i, segment) in line.split('\t').enumerate() {
+            if i > 0 {
+                // the tab itself is a space at the end of the text before it
+                let tab = self.font().glyph(' ').scaled(line_scale.scale);
+                let space = tab.h_metrics().advance_width;
+                glyphs.push(tab.positioned(point(origin.x + pen, origin.y)));
+
+                pen = match self.tab_stops.next_stop(pen / unit) {
+                    Some(stop) => {
+                        let aligned = match stop.alignment {
+                            TabAlignment::Left    => "",
+                            TabAlignment::Right   => segment,
+                            TabAlignment::Decimal => &segment[..segment.find('.').unwrap_or(segment.len())],
+                        };
+                        let aligned_width = self.layout_segment(aligned, line_scale, point(0.0, 0.0)).1;
+                        (stop.position * unit - aligned_width).max(pen)
+                    }
+                    None => pen + space,
+                };
+            }
+            let (segment_glyphs, end) = self.layout_segment(segment, line_scale, point(origin.x + pen, origin.y));
+            glyphs.extend(segment_glyphs);
+            pen = end - origin.x;
+        }
+        (glyphs, pen)
+    }
+
+    /// Lays out text without tabs or line breaks as one glyph per char, and returns the glyphs and where the glyph after them would go
+    fn layout_segment(&self, text: &str, line_scale: &LineScale, origin: Point<f32>) -> (Vec<PositionedGlyph<'static>>, f32) {
+        let LineScale { scale, unit, .. } = *line_scale;
+        let letter_spacing = self.spacing.letter.scaled(scale, unit) + self.synthesis.bold_width(scale);
+        let word_spacing = self.spacing.word.scaled(scale, unit);
+
+        let mut glyphs: Vec<PositionedGlyph<'static>> = vec!();
+        let mut x = origin.x;
+        let mut last: Option<(GlyphId, bool)> = None;
+        // letter spacing goes between grapheme clusters, so combining marks stay on their base
+        let chars = text.graphemes(true).flat_map(|grapheme| grapheme.chars().enumerate());
+        for (index_in_grapheme, c) in chars {
+            let glyph = self.font().glyph(c).scaled(scale);
+            let fixed_advance = self.fixed_advance(c, line_scale);
+            let fixed = fixed_advance.is_some();
+            // only glyphs that keep their own advance are kerned
+            if let Some((last_id, last_fixed)) = last {
+                if !fixed && !last_fixed {
+                    x += self.font().pair_kerning(scale, last_id, glyph.id());
+                }
+                if index_in_grapheme == 0 {
+                    x += letter_spacing;
+                }
+            }
+            last = Some((glyph.id(), fixed));
+
+            let own_advance = glyph.h_metrics().advance_width;
+            match fixed_advance {
+                Some(fixed_advance) => {
+                    glyphs.push(glyph.positioned(point(x + (fixed_advance - own_advance) / 2.0, origin.y)));
+                    x += fixed_advance;
+                }
+                None => {
+                    glyphs.push(glyph.positioned(point(x, origin.y)));
+                    x += own_advance;
+                }
+            }
+            if c == ' ' || c == '\u{a0}' {
+                x += word_spacing;
+            }
+        }
+        (glyphs, x)
+    }
+
+    /// `unit` is the width of a logical pixel in the units of `scale`
+    fn line_scale(&self, scale: Scale, unit: f32) -> LineScale {
+        let digit_advance = match self.monospace {
+            Monospace::Digits => {
+                let advance = |digit: char| self.font().glyph(digit).scaled(scale).h_metrics().advance_width;
+                ('0'..='9').map(advance).fold(0.0, f32::max)
+            }
+            _ => 0.0,
+        };
+        LineScale { scale, unit, digit_advance }
+    }
+
+    /// The advance of `c` if `monospace` fixes it, the glyph is centered in it
+    fn fixed_advance(&self, c: char, line_scale: &LineScale) -> Option<f32> {
+        match self.monospace {
+            Monospace::Digits if c.is_ascii_digit() => Some(line_scale.digit_advance),
+            // tabs are placed by the tab stops
+            Monospace::All(width) if c != '\t' => Some(width * line_scale.scale.x),
+            _ => None,
+        }
+    }
+
+    /// Where the cell of a laid out glyph starts and how wide it is, glyphs with a fixed advance are centered in their cell
+    fn glyph_cell(&self, c: char, glyph: &PositionedGlyph<'static>, line_scale: &LineScale) -> (f32, f32) {
+        let own_advance = glyph.unpositioned().h_metrics().advance_width;
+        match self.fixed_advance(c, line_scale) {
+            Some(fixed_advance) => (glyph.position().x - (fixed_advance - own_advance) / 2.0, fixed_advance),
+            None                => (glyph.position().x, own_advance),
+        }
+    }
+
+    /// Measures the lines of the laid out glyphs in logical pixels
+    fn lines(&self, data: &TextData, x: f32, y: f32, size: f32, text: &str, line_ranges: &[Range<usize>]) -> Vec<Line> {
+        let pixel_width = if self.subpixel { 3.0 } else { 1.0 };
+        let to_logical = |x: f32| x / (pixel_width * self.scale_factor);
+        let origin_x = match self.snapping {
+            Snapping::None => x,
+            _              => (x * self.scale_factor).round() / self.scale_factor,
+        };
+
+        let v_metrics = self.font().v_metrics(Scale::uniform(size));
+        let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+        // the scale the glyphs were laid out at by `layout_lines`
+        let scale = Scale { x: size * self.scale_factor * pixel_width, y: size * self.scale_factor };
+        let line_scale = self.line_scale(scale, self.scale_factor * pixel_width);
+
+        // the byte index of the start of every grapheme cluster, the index of a grapheme is its position in this list
+        let boundaries: Vec<usize> = text.grapheme_indices(true).map(|(i, _)| i).collect();
+        let grapheme_at = |byte_index: usize| boundaries.partition_point(|&boundary| boundary < byte_index);
+
+        let mut glyphs = data.glyphs.iter();
+        let mut glyph_index = 0;
+        let mut lines = vec!();
+        for (i, line) in line_ranges.iter().enumerate() {
+            // every char was laid out as one glyph
+            let mut carets = vec!();
+            let mut end_x = origin_x;
+            for ((byte_index, c), glyph) in text[line.clone()].char_indices().zip(glyphs.by_ref()) {
+                // carets go between the cells of glyphs centered in a fixed advance
+                let (cell_x, advance) = self.glyph_cell(c, glyph, &line_scale);
+                let glyph_x = to_logical(cell_x);
+                let byte_index = line.start + byte_index;
+                if boundaries.binary_search(&byte_index).is_ok() {
+                    carets.push(Caret { grapheme: grapheme_at(byte_index), glyph: glyph_index, x: glyph_x });
+                }
+                end_x = glyph_x + to_logical(advance);
+                glyph_index += 1;
+            }
+            carets.push(Caret { grapheme: grapheme_at(line.end), glyph: glyph_index, x: end_x });
+
+            let top = y - v_metrics.ascent + line_height * i as f32;
+            let line_break = text[line.end..].starts_with('\n') || text[line.end..].starts_with("\r\n");
+            lines.push(Line { top, bottom: top + line_height, carets, line_break });
+        }
+        lines
+    }
+}
+
+/// The scale lines are laid out at and the advances that depend on it, computed once for all lines laid out together
+#[derive(Clone, Copy)]
+struct LineScale {
+    scale:         Scale,
+    /// The width of a logical pixel in the units of `scale`
+    unit:          f32,
+    /// The advance of the widest digit with `Monospace::Digits`
+    digit_advance: f32,
+}
+
+/// Where and at what size `Layout::layout_scaled` lays out text, in physical pixels
+#[derive(Clone, Copy)]
+struct Placement {
+    /// The start of the first baseline
+    x:    f32,
+    y:    f32,
+    /// The horizontal and vertical size of the text
+    size: [f32; 2],
+    /// Horizontal physical pixels per logical pixel, tab stops and spacing given in logical pixels are multiplied by it
+    unit: f32,
+}
+
+/// The byte range of every line in `text` without its `'\n'` or `"\r\n"` line break
+fn line_ranges_of(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec!();
+    let mut start = 0;
+    for line in text.split('\n') {
+        let content = line.strip_suffix('\r').unwrap_or(line);
+        ranges.push(start..start + content.len());
+        start += line.len() + 1;
+    }
+    ranges
+}
+
+impl LaidOutText {
+    /// The number of lines laid out, including lines made by wrapping
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// The byte index in the text where layout stopped, the length of the text unless lines were cut off by `WrapOptions::max_lines`
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Whether lines were cut off by `WrapOptions::max_lines`, e.g. to show a button revealing the rest of the text
+    pub fn overflowed(&self) -> bool {
+        self.end < self.text.len()
+    }
+
+    /// Finds the grapheme cluster at a point given in the same logical pixels the text was laid out in.
+    /// Points above, below or beside the text hit the nearest line and the nearest grapheme cluster on it.
+    pub fn hit_test(&self, x: f32, y: f32) -> Hit {
+        let line = self.lines.iter().find(|line| y < line.bottom).unwrap_or_else(|| self.lines.last().unwrap());
+        let carets = &line.carets;
+        let start = carets[0];
+        let end = carets[carets.len() - 1];
+        let inside = y >= line.top && y < line.bottom && x >= start.x && x < end.x;
+
+        for pair in carets.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            if x < right.x {
+                return Hit { grapheme: left.grapheme, trailing: x >= (left.x + right.x) / 2.0, inside };
+            }
+        }
+
+        // past the end of the line
+        if carets.len() > 1 {
+            Hit { grapheme: carets[carets.len() - 2].grapheme, trailing: true, inside }
+        }
+        else {
+            Hit { grapheme: start.grapheme, trailing: false, inside }
+        }
+    }
+
+    /// The rectangle of a 1 logical pixel wide caret placed before the grapheme cluster at `index`, spanning the height of its line.
+    /// `index` may be the number of grapheme clusters to place the caret at the end of the text.
+    pub fn caret_rect(&self, index: usize) -> Rect {
+        let (line, caret) = self.caret_position(index);
+        Rect { x: caret.x, y: line.top, width: 1.0, height: line.bottom - line.top }
+    }
+
+    /// The rectangles covering the grapheme clusters in `range` with one rectangle per line, each spanning the height of its line.
+    /// Selected line breaks extend the rectangle of their line by the width of a space, wrapped lines end at their last grapheme cluster.
+    pub fn selection_rects(&self, range: Range<usize>) -> Vec<Rect> {
+        let mut rects = vec!();
+        for line in &self.lines {
+            let line_start = line.carets[0];
+            let line_end = line.carets[line.carets.len() - 1];
+            if range.end <= line_start.grapheme || range.start > line_end.grapheme || range.start >= range.end {
+                continue;
+            }
+
+            let start = line.carets.iter().find(|caret| caret.grapheme >= range.start).unwrap().x;
+            let end = if range.end <= line_end.grapheme {
+                line.carets.iter().find(|caret| caret.grapheme >= range.end).unwrap().x
+            }
+            else if line.line_break {
+                line_end.x + self.line_break_width
+            }
+            else {
+                line_end.x
+            };
+
+            if end > start {
+                rects.push(Rect { x: start, y: line.top, width: end - start, height: line.bottom - line.top });
+            }
+        }
+        rects
+    }
+
+    /// The range of glyphs making up the grapheme clusters in `range`
+    pub(crate) fn glyph_range(&self, range: Range<usize>) -> Range<usize> {
+        let start = self.caret_position(range.start).1.glyph;
+        let end = self.caret_position(range.end).1.glyph;
+        start..end.max(start)
+    }
+
+    /// The line containing the grapheme cluster boundary at `index` and the boundary, indices past the end are at the end of the last line
+    fn caret_position(&self, index: usize) -> (&Line, Caret) {
+        for line in &self.lines {
+            if let Some(caret) = line.carets.iter().find(|caret| caret.grapheme >= index) {
+                return (line, *caret);
+            }
+        }
+        let line = self.lines.last().unwrap();
+        (line, line.carets[line.carets.len() - 1])
+    }
+
+    /// Lays the text out again if `layout` has different settings than the `Layout` this text was laid out with,
+    /// so that hit testing and the caret and selection rectangles match the text as drawn. Queueing the text does this too.
+    pub fn update(&mut self, layout: &Layout) {
+        if self.generation != layout.generation {
+            *self = layout.layout_styled_text(self.x, self.y, &self.style, &self.text, &self.wrap_options);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glyph_cache::GlyphCache;
+    use crate::tab_stops::TabStop;
+
+    #[test]
+    fn line_breaks() {
+        let layout = Layout::new();
+        let data = layout.layout(10.0, 20.0, 16.0, [1.0; 4], "ab\r\nc");
+        let v_metrics = layout.font().v_metrics(Scale::uniform(16.0));
+        let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+
+        assert_eq!(data.glyphs.len(), 3);
+        assert_eq!(data.glyphs[2].position(), point(10.0, 20.0 + line_height));
+    }
+
+    #[test]
+    fn tab_interval() {
+        let mut layout = Layout::new();
+        let text = layout.layout_text(0.0, 20.0, 16.0, [1.0; 4], "ab\tc");
+        assert_eq!(text.caret_rect(3).x, 64.0);
+
+        // a tab exactly at a stop moves to the next one
+        let width = layout.text_width(16.0, "ab");
+        layout.tab_stops = TabStops::Interval(width);
+        let text = layout.layout_text(0.0, 20.0, 16.0, [1.0; 4], "ab\tc");
+        assert_eq!(text.caret_rect(3).x, width * 2.0);
+
+        // without stops a tab is as wide as a space
+        layout.tab_stops = TabStops::Interval(0.0);
+        let space = layout.font().glyph(' ').scaled(Scale::uniform(16.0)).h_metrics().advance_width;
+        let text = layout.layout_text(0.0, 20.0, 16.0, [1.0; 4], "ab\tc");
+        assert!((text.caret_rect(3).x - (width + space)).abs() < 0.001);
+    }
+
+    #[test]
+    fn tab_alignment() {
+        let mut layout = Layout::new();
+        let stop = |position, alignment| TabStop { position, alignment };
+
+        layout.tab_stops = TabStops::Stops(vec!(stop(100.0, TabAlignment::Right)));
+        assert!((layout.text_width(16.0, "a\tbcd") - 100.0).abs() < 0.001);
+
+        // the '.' is at the stop
+        layout.tab_stops = TabStops::Stops(vec!(stop(100.0, TabAlignment::Decimal)));
+        let text = layout.layout_text(0.0, 20.0, 16.0, [1.0; 4], "a\t12.5");
+        assert!((text.caret_rect(4).x - 100.0).abs() < 0.001);
+        // or the end of text without one
+        assert!((layout.text_width(16.0, "a\t125") - 100.0).abs() < 0.001);
+
+        // past the last stop a tab is as wide as a space
+        layout.tab_stops = TabStops::Stops(vec!(stop(30.0, TabAlignment::Left)));
+        let space = layout.font().glyph(' ').scaled(Scale::uniform(16.0)).h_metrics().advance_width;
+        let text = layout.layout_text(0.0, 20.0, 16.0, [1.0; 4], "a\tb\tc");
+        assert_eq!(text.caret_rect(2).x, 30.0);
+        let b = layout.text_width(16.0, "b");
+        assert!((text.caret_rect(4).x - (30.0 + b + space)).abs() < 0.001);
+    }
+
+    #[test]
+    fn truncate() {
+        let layout = Layout::new();
+        let text = "Sword of the Ancients";
+        let max_width = layout.text_width(16.0, text) / 2.0;
+        assert_eq!(layout.truncate(16.0, max_width * 2.0, Truncation::End, "…", text), text);
+
+        for truncation in [Truncation::Start, Truncation::Middle, Truncation::End].iter() {
+            let truncated = layout.truncate(16.0, max_width, *truncation, "…", text);
+            assert!(layout.text_width(16.0, &truncated) <= max_width);
+            let (start, end) = truncated.split_at(truncated.find('…').unwrap());
+            let end = &end['…'.len_utf8()..];
+            assert!(text.starts_with(start) && text.ends_with(end));
+            match truncation {
+                Truncation::Start  => assert!(start.is_empty()),
+                Truncation::Middle => assert!(start.len() == end.len() || start.len() == end.len() + 1),
+                Truncation::End    => assert!(end.is_empty()),
+            }
+        }
+    }
+
+    #[test]
+    fn wrap_long_word() {
+        let layout = Layout::new();
+        // the whole word only overflows once its last glyph is placed, after the line was already broken before the word
+        // "mmm" alone is too wide, but only found to be once the line was broken before it
+        let line = "i mmm i";
+        let max_width = layout.text_width(16.0, "i mm") + 0.5;
+
+        let ranges = layout.wrap(16.0, max_width, line);
+        let lines: Vec<&str> = ranges.iter().map(|range| &line[range.clone()]).collect();
+        assert_eq!(lines, ["i ", "mm", "m i"]);
+        for line in lines {
+            assert!(layout.text_width(16.0, line.trim_end()) <= max_width);
+        }
+    }
+
+    #[test]
+    fn wrap_monospace() {
+        let mut layout = Layout::new();
+        // the cells glyphs are centered in are measured, not the glyphs
+        layout.monospace = Monospace::All(2.0);
+        let options = WrapOptions { max_width: Some(85.0), max_lines: None };
+        let text = layout.layout_wrapped_text(0.0, 20.0, 10.0, [1.0; 4], "iiiiiiiii", &options);
+        assert_eq!(text.line_count(), 3);
+        for rect in text.selection_rects(0..9) {
+            assert!(rect.width <= 85.0, "{:?}", rect);
+        }
+
+        layout.monospace = Monospace::Digits;
+        let digit = layout.text_width(10.0, "1");
+        let options = WrapOptions { max_width: Some(digit * 3.5), max_lines: None };
+        let text = layout.layout_wrapped_text(0.0, 20.0, 10.0, [1.0; 4], "1111111", &options);
+        assert_eq!(text.line_count(), 3);
+    }
+
+    #[test]
+    fn max_lines() {
+        let mut layout = Layout::new();
+        layout.monospace = Monospace::All(1.0);
+        let options = |max_lines| WrapOptions { max_width: Some(40.0), max_lines };
+
+        let text = layout.layout_wrapped_text(0.0, 20.0, 16.0, [1.0; 4], "ab cd ef", &options(None));
+        assert_eq!((text.line_count(), text.end(), text.overflowed()), (3, 8, false));
+        let text = layout.layout_wrapped_text(0.0, 20.0, 16.0, [1.0; 4], "ab cd ef", &options(Some(3)));
+        assert_eq!((text.line_count(), text.end(), text.overflowed()), (3, 8, false));
+        // wrapped lines count towards the limit
+        let text = layout.layout_wrapped_text(0.0, 20.0, 16.0, [1.0; 4], "ab cd ef", &options(Some(2)));
+        assert_eq!((text.line_count(), text.end(), text.overflowed()), (2, 6, true));
+        // at least one line is laid out
+        let text = layout.layout_wrapped_text(0.0, 20.0, 16.0, [1.0; 4], "ab cd ef", &options(Some(0)));
+        assert_eq!((text.line_count(), text.end(), text.overflowed()), (1, 3, true));
+
+        // blank lines count too, the rest starts at the first line cut off
+        let text = layout.layout_wrapped_text(0.0, 20.0, 16.0, [1.0; 4], "a\n\n\nb", &options(Some(2)));
+        assert_eq!((text.line_count(), text.end(), text.overflowed()), (2, 3, true));
+    }
+
+    #[test]
+    fn monospace_digits() {
+        let mut layout = Layout::new();
+        layout.monospace = Monospace::Digits;
+        let width = layout.text_width(16.0, "1111");
+        assert!((width - layout.text_width(16.0, "0000")).abs() < 0.001);
+        assert!((width - layout.text_width(16.0, "8181")).abs() < 0.001);
+
+        // carets are between the cells the digits are centered in
+        let text = layout.layout_text(0.0, 20.0, 16.0, [1.0; 4], "1111");
+        assert!((text.caret_rect(2).x - width / 2.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn letter_spacing_between_graphemes() {
+        let layout = Layout::new().with_style(&TextStyle {
+            spacing: Spacing { letter: Length::Pixels(10.0), word: Length::Pixels(0.0) },
+            ..TextStyle::new(16.0, [1.0; 4])
+        });
+        let precomposed = layout.text_width(16.0, "a\u{e9}b");
+        let decomposed = layout.text_width(16.0, "ae\u{301}b");
+        assert!((precomposed - decomposed).abs() < 0.01, "{} != {}", precomposed, decomposed);
+    }
+
+    #[test]
+    fn update_after_settings_change() {
+        let mut glyph_cache = GlyphCache::new();
+        let mut text = glyph_cache.layout.layout_text(10.0, 20.0, 16.0, [1.0; 4], "abc\nde");
+
+        glyph_cache.set_monospace(Monospace::All(1.0));
+        glyph_cache.queue_laid_out_text(&mut text);
+        assert_eq!(text.caret_rect(3).x, 10.0 + 16.0 * 3.0);
+        assert_eq!(text.caret_rect(6).x, 10.0 + 16.0 * 2.0);
+        assert_eq!(text.hit_test(10.0 + 16.0 * 2.5, 20.0).grapheme, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "dejavu-sans-mono")]
+    fn combined_style() {
+        let layout = Layout::new();
+        let mono = TextStyle { face: Face::SansMono, ..TextStyle::new(24.0, [1.0; 4]) };
+        let styled = TextStyle {
+            spacing:   Spacing { letter: Length::Pixels(2.0), word: Length::Pixels(0.0) },
+            synthesis: Synthesis { bold: true, oblique: false },
+            ..mono
+        };
+        let mono_width = layout.with_style(&mono).text_width(24.0, "il");
+        assert!((mono_width - layout.with_style(&mono).text_width(24.0, "mw")).abs() < 0.001);
+
+        // the letter spacing and the width of a bold glyph, 1/24 of the size, between the two glyphs
+        let styled_width = layout.with_style(&styled).text_width(24.0, "il");
+        assert!((styled_width - (mono_width + 2.0 + 1.0)).abs() < 0.001);
+    }
+
+    #[test]
+    fn selection_across_line_break() {
+        let mut layout = Layout::new();
+        layout.monospace = Monospace::All(1.0);
+        let text = layout.layout_text(10.0, 20.0, 16.0, [1.0; 4], "ab\ncd");
+        let (top, bottom) = (text.caret_rect(0).y, text.caret_rect(3).y);
+        let rect = |x, y, width| Rect { x, y, width, height: 16.0 };
+
+        // the selected line break extends its line
+        let line_end = 42.0 + text.line_break_width;
+        assert_eq!(text.selection_rects(1..4), [rect(26.0, top, line_end - 26.0), rect(10.0, bottom, 16.0)]);
+        assert_eq!(text.selection_rects(2..3), [rect(42.0, top, line_end - 42.0)]);
+        assert_eq!(text.selection_rects(0..2), [rect(10.0, top, 32.0)]);
+        assert_eq!(text.selection_rects(2..2), []);
+        assert_eq!(text.selection_rects(Range { start: 3, end: 1 }), []);
+    }
+
+    #[test]
+    fn selection_across_wrap() {
+        let mut layout = Layout::new();
+        layout.monospace = Monospace::All(1.0);
+        let options = WrapOptions { max_width: Some(40.0), max_lines: None };
+        let text = layout.layout_wrapped_text(10.0, 20.0, 16.0, [1.0; 4], "ab cd", &options);
+        let (top, bottom) = (text.caret_rect(0).y, text.caret_rect(4).y);
+        let rect = |x, y, width| Rect { x, y, width, height: 16.0 };
+
+        // the wrapped line ends after the space it was broken at, without extending past it
+        assert_eq!(text.selection_rects(1..4), [rect(26.0, top, 32.0), rect(10.0, bottom, 16.0)]);
+        assert_eq!(text.selection_rects(2..3), [rect(42.0, top, 16.0)]);
+        assert_eq!(text.selection_rects(3..5), [rect(10.0, bottom, 32.0)]);
+
+        // a caret at the wrap boundary stays at the end of the wrapped line
+        assert_eq!(text.caret_rect(3), Rect { x: 58.0, y: top, width: 1.0, height: 16.0 });
+        assert_eq!(text.caret_rect(4), Rect { x: 26.0, y: bottom, width: 1.0, height: 16.0 });
+    }
+}
@@ -1,5 +1,33 @@
-use rusttype::{Font, PositionedGlyph, Scale, Rect, point};
-use rusttype::gpu_cache::Cache;
+mod clip;
+mod color;
+mod cpu;
+mod face;
+mod glyph_cache;
+mod label;
+mod layout;
+mod math;
+mod retained;
+mod selection;
+mod tab_stops;
+mod text_input;
+mod transform;
+
+pub use crate::color::{BlendMode, ColorSpace, Gamma};
+pub use crate::cpu::CpuDrawText;
+pub use crate::face::Face;
+pub use crate::glyph_cache::{Antialiasing, SubpixelOrder};
+pub use crate::label::LabelOptions;
+pub use crate::layout::{Hit, Layout, LaidOutText, Length, Monospace, Rect, Snapping, Spacing, Synthesis, TextStyle, Truncation, WrapOptions};
+pub use crate::retained::TextHandle;
+pub use crate::selection::SelectionStyle;
+pub use crate::tab_stops::{TabAlignment, TabStop, TabStops};
+pub use crate::text_input::{Clipboard, QueueText, TextInput};
+pub use crate::transform::Transform;
+
+use crate::clip::ClipStack;
+use crate::glyph_cache::{self, GlyphCache, TextData, Quad, CACHE_WIDTH, CACHE_HEIGHT};
+use crate::math::Matrix4;
+use crate::retained::RetainedTexts;
 
 use vulkano::buffer::{CpuAccessibleBuffer, BufferUsage};
 use vulkano::command_buffer::{DynamicState, AutoCommandBufferBuilder, SubpassContents, PrimaryAutoCommandBuffer};
@@ -7,24 +35,28 @@ use vulkano::descriptor::descriptor_set::{PersistentDescriptorSet};
 use vulkano::device::{Device, Queue};
 use vulkano::format::{Format, ClearValue};
 use vulkano::render_pass::{Framebuffer, FramebufferAbstract, Subpass, RenderPass};
-use vulkano::image::{SwapchainImage, ImmutableImage, ImageCreateFlags, ImageUsage, ImageLayout, ImageDimensions};
+use vulkano::image::{SwapchainImage, AttachmentImage, ImmutableImage, ImageAccess, ImageCreateFlags, ImageUsage, ImageLayout, ImageDimensions};
 use vulkano::image::view::ImageView;
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
+use vulkano::pipeline::depth_stencil::{DepthStencil, Compare};
 use vulkano::pipeline::vertex::SingleBufferDefinition;
-use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::viewport::{Viewport, Scissor};
 use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
 use vulkano::sampler::{Sampler, Filter, MipmapMode, SamplerAddressMode};
 use vulkano::swapchain::Swapchain;
 
 use std::iter;
+use std::ops::Range;
 use std::sync::Arc;
 
 #[derive(Default, Debug, Clone)]
 struct Vertex {
-    position:     [f32; 2],
+    position:     [f32; 3],
     tex_position: [f32; 2],
+    tex_bounds:   [f32; 4],
     color:        [f32; 4]
 }
-vulkano::impl_vertex!(Vertex, position, tex_position, color);
+vulkano::impl_vertex!(Vertex, position, tex_position, tex_bounds, color);
 
 mod vs {
     vulkano_shaders::shader!{
@@ -40,132 +72,465 @@ mod fs {
     }
 }
 
-struct TextData {
-    glyphs: Vec<PositionedGlyph<'static>>,
-    color:  [f32; 4],
+mod fs_subpixel {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        path: "src/shaders/fragment_subpixel.glsl",
+    }
+}
+
+struct Shaders {
+    vs:          vs::Shader,
+    fs:          fs::Shader,
+    fs_subpixel: fs_subpixel::Shader,
+}
+
+impl Shaders {
+    fn load(device: Arc<Device>) -> Shaders {
+        Shaders {
+            vs:          vs::Shader::load(device.clone()).unwrap(),
+            fs:          fs::Shader::load(device.clone()).unwrap(),
+            fs_subpixel: fs_subpixel::Shader::load(device).unwrap(),
+        }
+    }
 }
 
 pub struct DrawText {
     device:             Arc<Device>,
     queue:              Arc<Queue>,
-    font:               Font<'static>,
-    cache:              Cache<'static>,
-    cache_pixel_buffer: Vec<u8>,
+    glyph_cache:        GlyphCache,
+    target_srgb:        bool,
+    color_space:        ColorSpace,
+    gamma:              Gamma,
+    antialiasing:       Antialiasing,
+    blend_mode:         BlendMode,
+    shaders:            Shaders,
+    render_pass:        Arc<RenderPass>,
+    dimensions:         [u32; 2],
+    depth_buffer:       bool,
     pipeline:           Arc<GraphicsPipeline<SingleBufferDefinition<Vertex>>>,
+    /// Depth tested when there is a depth buffer
+    pipeline_world:     Arc<GraphicsPipeline<SingleBufferDefinition<Vertex>>>,
     framebuffers:       Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+    view_projection:    Matrix4,
+    clip_stack:         ClipStack,
     texts:              Vec<TextData>,
+    /// The vertex buffers are reused until the text changes, texts without any visible glyphs have none
+    retained:           RetainedTexts<Option<Arc<CpuAccessibleBuffer<[Vertex]>>>>,
 }
 
-const CACHE_WIDTH: usize = 1000;
-const CACHE_HEIGHT: usize = 1000;
-
 impl DrawText {
     pub fn new<W>(device: Arc<Device>, queue: Arc<Queue>, swapchain: Arc<Swapchain<W>>, images: &[Arc<SwapchainImage<W>>]) -> DrawText where W: Send + Sync + 'static {
-        let font_data = include_bytes!("DejaVuSans.ttf");
-        let font = Font::from_bytes(font_data as &[u8]).unwrap();
-
-        let vs = vs::Shader::load(device.clone()).unwrap();
-        let fs = fs::Shader::load(device.clone()).unwrap();
-
-        let cache = Cache::builder().dimensions(CACHE_WIDTH as u32, CACHE_HEIGHT as u32).build();
-        let cache_pixel_buffer = vec!(0; CACHE_WIDTH * CACHE_HEIGHT);
-
-        let render_pass = Arc::new(vulkano::single_pass_renderpass!(device.clone(),
-            attachments: {
-                color: {
-                    load: Load,
-                    store: Store,
-                    format: swapchain.format(),
-                    samples: 1,
+        DrawText::create(device, queue, swapchain, images, None)
+    }
+
+    /// Like `new` but text queued with `queue_text_3d` is depth tested against `depth_buffer`.
+    /// `depth_buffer` must be the depth attachment of your own render pass and that render pass must store its contents.
+    pub fn new_with_depth<W>(device: Arc<Device>, queue: Arc<Queue>, swapchain: Arc<Swapchain<W>>, images: &[Arc<SwapchainImage<W>>], depth_buffer: Arc<AttachmentImage>) -> DrawText where W: Send + Sync + 'static {
+        DrawText::create(device, queue, swapchain, images, Some(depth_buffer))
+    }
+
+    fn create<W>(device: Arc<Device>, queue: Arc<Queue>, swapchain: Arc<Swapchain<W>>, images: &[Arc<SwapchainImage<W>>], depth_buffer: Option<Arc<AttachmentImage>>) -> DrawText where W: Send + Sync + 'static {
+        let target_srgb = is_srgb(swapchain.format());
+
+        let shaders = Shaders::load(device.clone());
+
+        let render_pass = match &depth_buffer {
+            Some(depth_buffer) => Arc::new(vulkano::single_pass_renderpass!(device.clone(),
+                attachments: {
+                    color: {
+                        load: Load,
+                        store: Store,
+                        format: swapchain.format(),
+                        samples: 1,
+                    },
+                    depth: {
+                        load: Load,
+                        store: Store,
+                        format: depth_buffer.format(),
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth}
                 }
-            },
-            pass: {
-                color: [color],
-                depth_stencil: {}
-            }
-        ).unwrap()) as Arc<RenderPass>;
+            ).unwrap()) as Arc<RenderPass>,
+            None => Arc::new(vulkano::single_pass_renderpass!(device.clone(),
+                attachments: {
+                    color: {
+                        load: Load,
+                        store: Store,
+                        format: swapchain.format(),
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {}
+                }
+            ).unwrap()) as Arc<RenderPass>,
+        };
 
         let framebuffers = images.iter().map(|image| {
             let view = ImageView::new(image.clone()).unwrap();
-            Arc::new(
-                Framebuffer::start(render_pass.clone())
-                .add(view).unwrap()
-                .build().unwrap()
-            ) as Arc<dyn FramebufferAbstract + Send + Sync>
+            match &depth_buffer {
+                Some(depth_buffer) => Arc::new(
+                    Framebuffer::start(render_pass.clone())
+                    .add(view).unwrap()
+                    .add(ImageView::new(depth_buffer.clone()).unwrap()).unwrap()
+                    .build().unwrap()
+                ) as Arc<dyn FramebufferAbstract + Send + Sync>,
+                None => Arc::new(
+                    Framebuffer::start(render_pass.clone())
+                    .add(view).unwrap()
+                    .build().unwrap()
+                ) as Arc<dyn FramebufferAbstract + Send + Sync>,
+            }
         }).collect::<Vec<_>>();
 
-        let pipeline = Arc::new(GraphicsPipeline::start()
-            .vertex_input_single_buffer()
-            .vertex_shader(vs.main_entry_point(), ())
-            .triangle_list()
-            .viewports(iter::once(Viewport {
-                origin:      [0.0, 0.0],
-                depth_range: 0.0..1.0,
-                dimensions:  [
-                    images[0].dimensions()[0] as f32,
-                    images[0].dimensions()[1] as f32
-                ],
-            }))
-            .fragment_shader(fs.main_entry_point(), ())
-            .blend_alpha_blending()
-            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-            .build(device.clone())
-            .unwrap()
-        );
+        let dimensions = images[0].dimensions();
+        let settings = PipelineSettings {
+            gamma:        Gamma::for_target(target_srgb),
+            antialiasing: Antialiasing::default(),
+            blend_mode:   BlendMode::default(),
+            depth_test:   false,
+        };
+        let pipeline = create_pipeline(device.clone(), &shaders, render_pass.clone(), dimensions, settings);
+        let pipeline_world = create_pipeline(device.clone(), &shaders, render_pass.clone(), dimensions, PipelineSettings { depth_test: depth_buffer.is_some(), .. settings });
 
         DrawText {
             device,
             queue,
-            font,
-            cache,
-            cache_pixel_buffer,
+            glyph_cache: GlyphCache::new(),
+            target_srgb,
+            color_space: ColorSpace::default(),
+            gamma: settings.gamma,
+            antialiasing: settings.antialiasing,
+            blend_mode: settings.blend_mode,
+            shaders,
+            render_pass,
+            dimensions,
+            depth_buffer: depth_buffer.is_some(),
             pipeline,
+            pipeline_world,
             framebuffers,
+            view_projection: math::IDENTITY,
+            clip_stack: ClipStack::default(),
             texts: vec!(),
+            retained: RetainedTexts::new(),
+        }
+    }
+
+    pub fn snapping(&self) -> Snapping {
+        self.glyph_cache.snapping()
+    }
+
+    /// Sets whether glyphs are moved to whole pixels, defaults to `Snapping::None`.
+    /// Applies to text queued afterwards, transformed and 3D text is snapped before it is transformed.
+    pub fn set_snapping(&mut self, snapping: Snapping) {
+        self.glyph_cache.set_snapping(snapping);
+    }
+
+    pub fn monospace(&self) -> Monospace {
+        self.glyph_cache.monospace()
+    }
+
+    /// Sets whether glyphs advance by a fixed width, defaults to `Monospace::None`.
+    /// Applies to text queued afterwards, use `Monospace::Digits` so scores and timers do not jump around as they count.
+    pub fn set_monospace(&mut self, monospace: Monospace) {
+        self.glyph_cache.set_monospace(monospace);
+    }
+
+    pub fn tab_stops(&self) -> &TabStops {
+        self.glyph_cache.tab_stops()
+    }
+
+    /// Sets where the text after a `'\t'` is placed, defaults to `TabStops::Interval(64.0)`.
+    /// Applies to text queued afterwards, e.g. `TabStops::Stops` with `TabAlignment::Decimal` lines up a column of scores.
+    pub fn set_tab_stops(&mut self, tab_stops: TabStops) {
+        self.glyph_cache.set_tab_stops(tab_stops);
+    }
+
+    /// The scale and position tolerances of the glyph cache
+    pub fn cache_tolerances(&self) -> (f32, f32) {
+        self.glyph_cache.tolerances()
+    }
+
+    /// Sets how close the size and subpixel position of a glyph must be to a cached glyph, in pixels, for the cached glyph to be reused.
+    /// Both default to 0.1, smaller values are more accurate but rasterize more glyphs.
+    /// Values below 0.001 are raised to 0.001 and a position tolerance of 1.0 or more reuses glyphs at any subpixel position.
+    /// All cached glyphs are thrown out when the tolerances change so avoid calling this every frame.
+    ///
+    /// Panics if either tolerance is negative.
+    pub fn set_cache_tolerances(&mut self, scale_tolerance: f32, position_tolerance: f32) {
+        self.glyph_cache.set_tolerances(scale_tolerance, position_tolerance);
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Sets how the colors passed to `queue_text` are interpreted, defaults to `ColorSpace::Srgb`
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        if color_space != self.color_space {
+            self.color_space = color_space;
+            self.retained.clear_cached();
         }
     }
 
+    pub fn gamma(&self) -> Gamma {
+        self.gamma
+    }
+
+    /// Sets how glyph coverage is adjusted, defaults to `Gamma::for_target` for the swapchain format.
+    /// The gamma is baked into the pipeline so avoid calling this every frame.
+    pub fn set_gamma(&mut self, gamma: Gamma) {
+        if gamma != self.gamma {
+            self.gamma = gamma;
+            self.rebuild_pipelines();
+        }
+    }
+
+    /// The antialiasing in use, which is `Antialiasing::Grayscale` if subpixel antialiasing was requested but is unsupported
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.antialiasing
+    }
+
+    /// Sets how glyph edges are smoothed, defaults to `Antialiasing::Grayscale`.
+    /// `Antialiasing::Subpixel` requires the `dual_src_blend` device feature to be enabled, otherwise it falls back to `Antialiasing::Grayscale`.
+    /// The antialiasing is baked into the pipeline so avoid calling this every frame.
+    pub fn set_antialiasing(&mut self, antialiasing: Antialiasing) {
+        let antialiasing = if self.device.enabled_features().dual_src_blend {
+            antialiasing
+        }
+        else {
+            Antialiasing::Grayscale
+        };
+
+        if antialiasing != self.antialiasing {
+            self.antialiasing = antialiasing;
+            self.glyph_cache.set_subpixel(antialiasing != Antialiasing::Grayscale);
+            self.rebuild_pipelines();
+        }
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Sets how text is blended with the swapchain image, defaults to `BlendMode::Alpha`.
+    /// The blend mode is baked into the pipeline so avoid calling this every frame.
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        if blend_mode != self.blend_mode {
+            self.blend_mode = blend_mode;
+            self.rebuild_pipelines();
+        }
+    }
+
+    fn rebuild_pipelines(&mut self) {
+        let settings = PipelineSettings {
+            gamma:        self.gamma,
+            antialiasing: self.antialiasing,
+            blend_mode:   self.blend_mode,
+            depth_test:   false,
+        };
+        self.pipeline = create_pipeline(self.device.clone(), &self.shaders, self.render_pass.clone(), self.dimensions, settings);
+        self.pipeline_world = create_pipeline(self.device.clone(), &self.shaders, self.render_pass.clone(), self.dimensions, PipelineSettings { depth_test: self.depth_buffer, .. settings });
+    }
+
+    pub fn scale_factor(&self) -> f32 {
+        self.glyph_cache.scale_factor()
+    }
+
+    /// Sets the number of physical pixels per logical pixel, defaults to 1.0.
+    ///
+    /// Positions, sizes, offsets and clip rectangles are given in logical pixels and glyphs are rasterized at physical resolution.
+    /// Call this with `window.scale_factor() as f32` after creating `DrawText` and whenever winit reports `WindowEvent::ScaleFactorChanged`.
+    /// Applies to text queued afterwards.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.glyph_cache.set_scale_factor(scale_factor);
+    }
+
+    pub fn view_projection(&self) -> [[f32; 4]; 4] {
+        self.view_projection
+    }
+
+    /// Sets the column major view-projection matrix that text queued with `queue_text_3d` is rendered with and `queue_label` projects with.
+    /// Defaults to the identity matrix.
+    pub fn set_view_projection(&mut self, view_projection: [[f32; 4]; 4]) {
+        self.view_projection = view_projection;
+    }
+
+    /// Text queued until the matching `pop_clip` is only drawn inside the given rectangle, in screen pixels.
+    /// Nested clip rectangles are intersected with the enclosing ones, glyphs crossing the edges are cut at the pixel boundary.
+    pub fn push_clip(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let scale = self.glyph_cache.scale_factor();
+        self.clip_stack.push(x * scale, y * scale, width * scale, height * scale);
+    }
+
+    /// Removes the clip rectangle added by the last call to `push_clip`
+    pub fn pop_clip(&mut self) {
+        self.clip_stack.pop();
+    }
+
+    fn push_text(&mut self, mut text: TextData) {
+        text.clip = self.clip_stack.current();
+        self.texts.push(text);
+    }
+
+    /// Queues text whose first baseline starts at `(x, y)`, in logical pixels.
+    ///
+    /// Lines are broken at every `'\n'` or `"\r\n"`, each line one line height below the one before it.
+    /// Versions before `Layout` was added drew the line break as a glyph and kept the text on one line.
     pub fn queue_text(&mut self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str) {
-        let glyphs: Vec<PositionedGlyph> = self.font.layout(text, Scale::uniform(size), point(x, y)).map(|x| x.standalone()).collect();
-        for glyph in &glyphs {
-            self.cache.queue_glyph(0, glyph.clone());
+        let text = self.glyph_cache.queue_text(x, y, size, color, text);
+        self.push_text(text);
+    }
+
+    /// Like `queue_text` but lines wider than `max_width` logical pixels are shortened to fit with a `"…"`, e.g. for item names in fixed size slots.
+    /// The text is drawn in `style` like `queue_text_styled`, use `Layout::truncate` for another suffix.
+    pub fn queue_text_truncated(&mut self, x: f32, y: f32, style: &TextStyle, max_width: f32, truncation: Truncation, text: &str) {
+        let text = self.glyph_cache.layout.with_style(style).truncate(style.size, max_width, truncation, "…", text);
+        self.queue_text_styled(x, y, style, &text);
+    }
+
+    /// Like `queue_text` but in the size, color, face, spacing, synthesis and depth of `style`, which can be combined freely,
+    /// e.g. synthetic bold `Face::SansMono` with extra letter spacing.
+    /// Use `Layout::with_style` to measure or lay out styled text.
+    pub fn queue_text_styled(&mut self, x: f32, y: f32, style: &TextStyle, text: &str) {
+        let text = self.glyph_cache.queue_text_styled(x, y, style, text);
+        self.push_text(text);
+    }
+
+    /// Like `queue_text_styled` but the laid out text is transformed in screen space, e.g. by `Transform::rotation(angle).around(x, y)` to rotate it around its start.
+    /// Glyphs are rasterized at a resolution matching the scale of the transform.
+    pub fn queue_text_transformed(&mut self, x: f32, y: f32, style: &TextStyle, text: &str, transform: Transform) {
+        let text = self.glyph_cache.queue_text_transformed(x, y, style, text, transform);
+        self.push_text(text);
+    }
+
+    /// A `Layout` with the current scale factor, snapping and antialiasing, for laying out text on other threads.
+    /// Text it lays out is laid out again when queued if any of these settings changed in the meantime.
+    pub fn layout(&self) -> Layout {
+        self.glyph_cache.layout.clone()
+    }
+
+    /// Queues text laid out by a `Layout` to be drawn like text queued with `queue_text`.
+    /// The text can be kept and queued again every frame, its glyphs are reused unless the layout settings changed,
+    /// in which case it is laid out again along with the geometry used by `LaidOutText::hit_test` and `LaidOutText::caret_rect`.
+    pub fn queue_laid_out_text(&mut self, text: &mut LaidOutText) {
+        let text = self.glyph_cache.queue_laid_out_text(text);
+        self.push_text(text);
+    }
+
+    /// Like `queue_laid_out_text` but also draws the grapheme clusters in `selection` highlighted and a caret before the grapheme cluster at `caret`.
+    ///
+    /// The highlight is drawn behind the text and the caret above it, in the same draw as the glyphs.
+    /// `blink_time` is the time in seconds since the caret last moved, the caret blinks as set by `style`.
+    /// Indices are grapheme cluster indices as used by `LaidOutText::hit_test` and `LaidOutText::caret_rect`.
+    pub fn queue_selected_text(&mut self, text: &mut LaidOutText, selection: Range<usize>, caret: Option<usize>, blink_time: f32, style: &SelectionStyle) {
+        let text = self.glyph_cache.queue_selected_text(text, selection, caret, blink_time, style);
+        self.push_text(text);
+    }
+
+    /// Queues laid out text containing an IME composition (preedit) string, which is underlined in the text color.
+    ///
+    /// `preedit` is the range of the composition within the text and `cursor` the IME's cursor within the text, the clause it covers is underlined thicker and the caret drawn at its end.
+    /// The caret is hidden when `cursor` is `None`, its rectangle from `LaidOutText::caret_rect` tells the IME where to put its candidate window.
+    /// Indices are grapheme cluster indices as used by `LaidOutText::hit_test` and `LaidOutText::caret_rect`.
+    pub fn queue_preedit_text(&mut self, text: &mut LaidOutText, preedit: Range<usize>, cursor: Option<Range<usize>>, blink_time: f32, style: &SelectionStyle) {
+        let text = self.glyph_cache.queue_preedit_text(text, preedit, cursor, blink_time, style);
+        self.push_text(text);
+    }
+
+    /// Queues text to be drawn in world space, depth tested if `DrawText` was created with `new_with_depth`.
+    ///
+    /// The text is laid out in a local space where the start of the baseline is at the origin, x points right, y points up and one unit is one logical pixel at `size`.
+    /// `model` is a column major matrix transforming that local space into world space, e.g. scale it down to make large, crisp glyphs fit on a small sign.
+    pub fn queue_text_3d(&mut self, model: [[f32; 4]; 4], size: f32, color: [f32; 4], text: &str) {
+        let mut text = self.glyph_cache.queue_text(0.0, 0.0, size, color, text);
+
+        // glyphs are laid out in physical pixels
+        let scale = self.glyph_cache.scale_factor();
+        let mut model = model;
+        for column in &mut model[..2] {
+            for value in column.iter_mut() {
+                *value /= scale;
+            }
         }
-        self.texts.push(TextData {
-            glyphs: glyphs.clone(),
-            color,
-        });
+        text.model = Some(model);
+        self.push_text(text);
+    }
+
+    /// Queues a label that always faces the screen at a constant size, horizontally centered over `world_position` projected by the view-projection matrix.
+    /// The label is depth tested at the depth of `world_position` if `DrawText` was created with `new_with_depth`.
+    /// Labels behind the camera or fully faded out are skipped.
+    pub fn queue_label(&mut self, world_position: [f32; 3], size: f32, color: [f32; 4], text: &str, options: LabelOptions) {
+        let projected = match label::project(&self.view_projection, self.dimensions, world_position) {
+            Some(projected) => projected,
+            None            => return,
+        };
+
+        let opacity = options.opacity(projected.distance);
+        if opacity <= 0.0 {
+            return;
+        }
+        let color = [color[0], color[1], color[2], color[3] * opacity];
+
+        // projected positions are in physical pixels
+        let scale = self.glyph_cache.scale_factor();
+        let x = projected.x / scale + options.offset[0] - self.glyph_cache.layout.text_width(size, text) / 2.0;
+        let y = projected.y / scale + options.offset[1];
+        let mut text = self.glyph_cache.queue_text(x, y, size, color, text);
+        text.depth = Some(projected.depth);
+        self.push_text(text);
+    }
+
+    /// Creates text that is drawn by every call to `draw_text` until it is removed with `remove_text`, below any queued text.
+    ///
+    /// Unlike queued text, the layout and vertices of retained text are kept and reused until the text is changed through its handle,
+    /// which makes it cheaper for text that rarely changes such as static labels.
+    pub fn create_text(&mut self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str) -> TextHandle {
+        self.retained.create(x, y, size, color, text)
+    }
+
+    pub fn remove_text(&mut self, handle: TextHandle) {
+        self.retained.remove(handle);
+    }
+
+    /// Panics if the text was removed, as do the other `set_text_*` methods
+    pub fn set_text_content(&mut self, handle: TextHandle, text: &str) {
+        self.retained.set_content(handle, text);
+    }
+
+    pub fn set_text_position(&mut self, handle: TextHandle, x: f32, y: f32) {
+        self.retained.set_position(handle, x, y);
+    }
+
+    pub fn set_text_size(&mut self, handle: TextHandle, size: f32) {
+        self.retained.set_size(handle, size);
+    }
+
+    /// Only the vertices are recreated, the layout is kept
+    pub fn set_text_color(&mut self, handle: TextHandle, color: [f32; 4]) {
+        self.retained.set_color(handle, color);
     }
 
     pub fn draw_text<'a>(&mut self, command_buffer: &'a mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, image_num: usize) -> &'a mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer> {
         let screen_width  = self.framebuffers[image_num].dimensions()[0];
         let screen_height = self.framebuffers[image_num].dimensions()[1];
-        let cache_pixel_buffer = &mut self.cache_pixel_buffer;
-        let cache = &mut self.cache;
 
         // update texture cache
-        cache.cache_queued(
-            |rect, src_data| {
-                let width = (rect.max.x - rect.min.x) as usize;
-                let height = (rect.max.y - rect.min.y) as usize;
-                let mut dst_index = rect.min.y as usize * CACHE_WIDTH + rect.min.x as usize;
-                let mut src_index = 0;
-
-                for _ in 0..height {
-                    let dst_slice = &mut cache_pixel_buffer[dst_index..dst_index+width];
-                    let src_slice = &src_data[src_index..src_index+width];
-                    dst_slice.copy_from_slice(src_slice);
-
-                    dst_index += CACHE_WIDTH;
-                    src_index += width;
-                }
-            }
-        ).unwrap();
+        self.retained.queue_glyphs(&mut self.glyph_cache);
+        self.glyph_cache.update();
+        glyph_cache::sort_by_depth(&mut self.texts);
 
         let buffer = CpuAccessibleBuffer::<[u8]>::from_iter(
             self.device.clone(),
             BufferUsage::all(),
             false,
-            cache_pixel_buffer.iter().cloned()
+            self.glyph_cache.pixels.iter().cloned()
         ).unwrap();
 
         let (cache_texture, cache_texture_write) = ImmutableImage::uninitialized(
@@ -202,74 +567,280 @@ impl DrawText {
             .build().unwrap()
         );
 
+        let mut clear_values = vec!(ClearValue::None);
+        if self.depth_buffer {
+            clear_values.push(ClearValue::None);
+        }
+
         let mut command_buffer = command_buffer
             .copy_buffer_to_image(
                 buffer,
                 cache_texture_write,
             ).unwrap()
-            .begin_render_pass(self.framebuffers[image_num].clone(), SubpassContents::Inline, vec!(ClearValue::None)).unwrap();
+            .begin_render_pass(self.framebuffers[image_num].clone(), SubpassContents::Inline, clear_values).unwrap();
 
         // draw
-        for text in &mut self.texts.drain(..) {
-            let vertices: Vec<Vertex> = text.glyphs.iter().flat_map(|g| {
-                if let Ok(Some((uv_rect, screen_rect))) = cache.rect_for(0, g) {
-                    let gl_rect = Rect {
-                        min: point(
-                            (screen_rect.min.x as f32 / screen_width  as f32 - 0.5) * 2.0,
-                            (screen_rect.min.y as f32 / screen_height as f32 - 0.5) * 2.0
-                        ),
-                        max: point(
-                           (screen_rect.max.x as f32 / screen_width  as f32 - 0.5) * 2.0,
-                           (screen_rect.max.y as f32 / screen_height as f32 - 0.5) * 2.0
-                        )
-                    };
-                    vec!(
-                        Vertex {
-                            position:     [gl_rect.min.x, gl_rect.max.y],
-                            tex_position: [uv_rect.min.x, uv_rect.max.y],
-                            color:        text.color,
-                        },
-                        Vertex {
-                            position:     [gl_rect.min.x, gl_rect.min.y],
-                            tex_position: [uv_rect.min.x, uv_rect.min.y],
-                            color:        text.color,
-                        },
-                        Vertex {
-                            position:     [gl_rect.max.x, gl_rect.min.y],
-                            tex_position: [uv_rect.max.x, uv_rect.min.y],
-                            color:        text.color,
-                        },
-
-                        Vertex {
-                            position:     [gl_rect.max.x, gl_rect.min.y],
-                            tex_position: [uv_rect.max.x, uv_rect.min.y],
-                            color:        text.color,
-                        },
-                        Vertex {
-                            position:     [gl_rect.max.x, gl_rect.max.y],
-                            tex_position: [uv_rect.max.x, uv_rect.max.y],
-                            color:        text.color,
-                        },
-                        Vertex {
-                            position:     [gl_rect.min.x, gl_rect.max.y],
-                            tex_position: [uv_rect.min.x, uv_rect.max.y],
-                            color:        text.color,
-                        },
-                    ).into_iter()
+        let screen = [screen_width, screen_height];
+        let full_screen = Scissor { origin: [0, 0], dimensions: screen };
+
+        // retained text is drawn below queued text
+        let glyph_cache = &self.glyph_cache;
+        let device = &self.device;
+        let (color_space, target_srgb) = (self.color_space, self.target_srgb);
+        for retained in self.retained.iter_mut() {
+            let vertex_buffer = retained.cached(glyph_cache, |text| {
+                let vertices = text_vertices(glyph_cache, text, color_space, target_srgb, screen);
+                if vertices.is_empty() {
+                    None
                 }
                 else {
-                    vec!().into_iter()
+                    Some(CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), false, vertices.into_iter()).unwrap())
+                }
+            });
+            if let Some(vertex_buffer) = vertex_buffer {
+                let push_constants = vs::ty::PushConstants { transform: math::IDENTITY };
+                command_buffer = command_buffer.draw(self.pipeline.clone(), &scissor_state(full_screen), vertex_buffer.clone(), set.clone(), push_constants, vec![]).unwrap();
+            }
+        }
+
+        for text in &mut self.texts.drain(..) {
+            let scissor = match text.clip {
+                Some(clip) => {
+                    let clip = clip.clamp(screen);
+                    if clip.is_empty() {
+                        continue;
+                    }
+                    Scissor {
+                        origin:     clip.min,
+                        dimensions: [(clip.max[0] - clip.min[0]) as u32, (clip.max[1] - clip.min[1]) as u32],
+                    }
                 }
-            }).collect();
+                None => full_screen,
+            };
+
+            let vertices = text_vertices(&self.glyph_cache, &text, self.color_space, self.target_srgb, screen);
+            if vertices.is_empty() {
+                continue;
+            }
+
+            let (pipeline, transform) = match (text.model, text.depth) {
+                (Some(_), _)    => (self.pipeline_world.clone(), self.view_projection),
+                (None, Some(_)) => (self.pipeline_world.clone(), math::IDENTITY),
+                (None, None)    => (self.pipeline.clone(), math::IDENTITY),
+            };
+            let push_constants = vs::ty::PushConstants { transform };
 
             let vertex_buffer = CpuAccessibleBuffer::from_iter(self.device.clone(), BufferUsage::all(), false, vertices.into_iter()).unwrap();
-            command_buffer = command_buffer.draw(self.pipeline.clone(), &DynamicState::none(), vertex_buffer.clone(), set.clone(), (), vec![]).unwrap();
+            command_buffer = command_buffer.draw(pipeline, &scissor_state(scissor), vertex_buffer.clone(), set.clone(), push_constants, vec![]).unwrap();
         }
 
         command_buffer.end_render_pass().unwrap()
     }
 }
 
+/// The vertices of every quad of the text, with colors converted from `color_space` for the target image
+fn text_vertices(glyph_cache: &GlyphCache, text: &TextData, color_space: ColorSpace, target_srgb: bool, screen: [u32; 2]) -> Vec<Vertex> {
+    glyph_cache.quads(text).into_iter().flat_map(|quad| {
+        let color = color::target_color(quad.color, color_space, target_srgb);
+        let corners = quad.corners(text.transform.as_ref());
+        let mut positions = [[0.0; 3]; 4];
+        for (position, corner) in positions.iter_mut().zip(corners.iter()) {
+            *position = match &text.model {
+                // y is flipped so the text is upright in a y up world
+                Some(model) => {
+                    let world = math::transform(model, [corner[0], -corner[1], 0.0, 1.0]);
+                    [world[0], world[1], world[2]]
+                }
+                None => [
+                    (corner[0] / screen[0] as f32 - 0.5) * 2.0,
+                    (corner[1] / screen[1] as f32 - 0.5) * 2.0,
+                    text.depth.unwrap_or(0.0)
+                ],
+            };
+        }
+        quad_vertices(&quad, positions, color).into_iter()
+    }).collect()
+}
+
+fn scissor_state(scissor: Scissor) -> DynamicState {
+    DynamicState {
+        scissors: Some(vec!(scissor)),
+        .. DynamicState::none()
+    }
+}
+
+/// `positions` are the bottom left, top left, top right and bottom right corners of the quad
+fn quad_vertices(quad: &Quad, positions: [[f32; 3]; 4], color: [f32; 4]) -> Vec<Vertex> {
+    let uv_rect = quad.uv;
+    let tex_bounds = [quad.uv_bounds.min.x, quad.uv_bounds.min.y, quad.uv_bounds.max.x, quad.uv_bounds.max.y];
+    vec!(
+        Vertex {
+            position:     positions[0],
+            tex_position: [uv_rect.min.x, uv_rect.max.y],
+            tex_bounds,
+            color,
+        },
+        Vertex {
+            position:     positions[1],
+            tex_position: [uv_rect.min.x, uv_rect.min.y],
+            tex_bounds,
+            color,
+        },
+        Vertex {
+            position:     positions[2],
+            tex_position: [uv_rect.max.x, uv_rect.min.y],
+            tex_bounds,
+            color,
+        },
+
+        Vertex {
+            position:     positions[2],
+            tex_position: [uv_rect.max.x, uv_rect.min.y],
+            tex_bounds,
+            color,
+        },
+        Vertex {
+            position:     positions[3],
+            tex_position: [uv_rect.max.x, uv_rect.max.y],
+            tex_bounds,
+            color,
+        },
+        Vertex {
+            position:     positions[0],
+            tex_position: [uv_rect.min.x, uv_rect.max.y],
+            tex_bounds,
+            color,
+        },
+    )
+}
+
+/// The state baked into a pipeline
+#[derive(Clone, Copy)]
+struct PipelineSettings {
+    gamma:        Gamma,
+    antialiasing: Antialiasing,
+    blend_mode:   BlendMode,
+    depth_test:   bool,
+}
+
+fn create_pipeline(device: Arc<Device>, shaders: &Shaders, render_pass: Arc<RenderPass>, dimensions: [u32; 2], settings: PipelineSettings) -> Arc<GraphicsPipeline<SingleBufferDefinition<Vertex>>> {
+    let PipelineSettings { gamma, antialiasing, blend_mode, depth_test } = settings;
+
+    // text is drawn over the scene without disturbing the depth buffer
+    let depth_stencil = if depth_test {
+        DepthStencil {
+            depth_compare: Compare::LessOrEqual,
+            depth_write:   false,
+            .. DepthStencil::disabled()
+        }
+    }
+    else {
+        DepthStencil::disabled()
+    };
+
+    let builder = GraphicsPipeline::start()
+        .vertex_input_single_buffer()
+        .vertex_shader(shaders.vs.main_entry_point(), ())
+        .triangle_list()
+        .viewports_fixed_scissors_dynamic(iter::once(Viewport {
+            origin:      [0.0, 0.0],
+            depth_range: 0.0..1.0,
+            dimensions:  [dimensions[0] as f32, dimensions[1] as f32],
+        }))
+        .depth_stencil(depth_stencil)
+        .render_pass(Subpass::from(render_pass, 0).unwrap());
+
+    match antialiasing {
+        Antialiasing::Grayscale => {
+            let fs_constants = fs::SpecializationConstants {
+                gamma:       gamma.gamma,
+                contrast:    gamma.contrast,
+                premultiply: (blend_mode != BlendMode::Alpha) as i32,
+            };
+            Arc::new(builder
+                .fragment_shader(shaders.fs.main_entry_point(), fs_constants)
+                .blend_collective(attachment_blend(blend_mode, antialiasing))
+                .build(device)
+                .unwrap()
+            )
+        }
+        Antialiasing::Subpixel(order) => {
+            let fs_constants = fs_subpixel::SpecializationConstants {
+                gamma:    gamma.gamma,
+                contrast: gamma.contrast,
+                bgr:      (order == SubpixelOrder::Bgr) as i32,
+            };
+            Arc::new(builder
+                .fragment_shader(shaders.fs_subpixel.main_entry_point(), fs_constants)
+                .blend_collective(attachment_blend(blend_mode, antialiasing))
+                .build(device)
+                .unwrap()
+            )
+        }
+    }
+}
+
+/// Premultiplied and additive blending expect fragment.glsl to premultiply its output
+fn attachment_blend(blend_mode: BlendMode, antialiasing: Antialiasing) -> AttachmentBlend {
+    let mut blend = match blend_mode {
+        BlendMode::Alpha => AttachmentBlend::alpha_blending(),
+        BlendMode::Premultiplied => AttachmentBlend {
+            color_source:      BlendFactor::One,
+            color_destination: BlendFactor::OneMinusSrcAlpha,
+            alpha_source:      BlendFactor::One,
+            alpha_destination: BlendFactor::OneMinusSrcAlpha,
+            .. AttachmentBlend::alpha_blending()
+        },
+        BlendMode::Additive => AttachmentBlend {
+            color_source:      BlendFactor::One,
+            color_destination: BlendFactor::One,
+            alpha_source:      BlendFactor::Zero,
+            alpha_destination: BlendFactor::One,
+            .. AttachmentBlend::alpha_blending()
+        },
+    };
+
+    // each color channel is blended with its own alpha from the second fragment shader output
+    if let Antialiasing::Subpixel(_) = antialiasing {
+        blend.color_source = BlendFactor::Src1Color;
+        if blend_mode != BlendMode::Additive {
+            blend.color_destination = BlendFactor::OneMinusSrc1Color;
+        }
+    }
+
+    blend
+}
+
+fn is_srgb(format: Format) -> bool {
+    matches!(format,
+        Format::R8Srgb |
+        Format::R8G8Srgb |
+        Format::R8G8B8Srgb |
+        Format::B8G8R8Srgb |
+        Format::R8G8B8A8Srgb |
+        Format::B8G8R8A8Srgb |
+        Format::A8B8G8R8SrgbPack32
+    )
+}
+
+impl QueueText for DrawText {
+    fn layout(&self) -> Layout {
+        DrawText::layout(self)
+    }
+
+    fn queue_laid_out_text(&mut self, text: &mut LaidOutText) {
+        DrawText::queue_laid_out_text(self, text);
+    }
+
+    fn queue_selected_text(&mut self, text: &mut LaidOutText, selection: Range<usize>, caret: Option<usize>, blink_time: f32, style: &SelectionStyle) {
+        DrawText::queue_selected_text(self, text, selection, caret, blink_time, style);
+    }
+
+    fn queue_preedit_text(&mut self, text: &mut LaidOutText, preedit: Range<usize>, cursor: Option<Range<usize>>, blink_time: f32, style: &SelectionStyle) {
+        DrawText::queue_preedit_text(self, text, preedit, cursor, blink_time, style);
+    }
+}
+
 impl DrawTextTrait for AutoCommandBufferBuilder<PrimaryAutoCommandBuffer> {
     fn draw_text(&mut self, data: &mut DrawText, image_num: usize) -> &mut Self {
         data.draw_text(self, image_num)
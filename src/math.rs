@@ -0,0 +1,19 @@
+/// Column major, matching glsl's `mat4` and the `to_cols_array_2d` of most math libraries
+pub(crate) type Matrix4 = [[f32; 4]; 4];
+
+pub(crate) const IDENTITY: Matrix4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+pub(crate) fn transform(matrix: &Matrix4, vector: [f32; 4]) -> [f32; 4] {
+    let mut result = [0.0; 4];
+    for (column, value) in matrix.iter().zip(vector.iter()) {
+        for (result, column) in result.iter_mut().zip(column.iter()) {
+            *result += column * value;
+        }
+    }
+    result
+}
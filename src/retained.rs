@@ -0,0 +1,169 @@
+use crate::glyph_cache::{GlyphCache, TextData};
+
+use std::collections::BTreeMap;
+
+/// Refers to text created with `DrawText::create_text`, which is drawn every frame until it is removed with `remove_text`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextHandle(u64);
+
+/// Text that is kept across frames along with its layout and `T`, the data a renderer derives from the layout.
+pub(crate) struct RetainedText<T> {
+    x:      f32,
+    y:      f32,
+    size:   f32,
+    color:  [f32; 4],
+    text:   String,
+    /// Laid out at `layout_generation`
+    layout: Option<(u64, TextData)>,
+    /// Created at `cache_generation`
+    cached: Option<(u64, T)>,
+}
+
+impl<T> RetainedText<T> {
+    /// Must be called after `RetainedTexts::queue_glyphs`
+    pub fn data(&self) -> &TextData {
+        &self.layout.as_ref().unwrap().1
+    }
+
+    /// Returns the cached renderer data, creating it if it is missing or the glyphs moved within the cache
+    pub fn cached<F>(&mut self, glyph_cache: &GlyphCache, create: F) -> &T where F: FnOnce(&TextData) -> T {
+        let generation = glyph_cache.cache_generation;
+        let valid = matches!(&self.cached, Some((cached_generation, _)) if *cached_generation == generation);
+        if !valid {
+            let cached = create(self.data());
+            self.cached = Some((generation, cached));
+        }
+        &self.cached.as_ref().unwrap().1
+    }
+}
+
+/// Retained texts in the order they were created
+pub(crate) struct RetainedTexts<T> {
+    texts:   BTreeMap<TextHandle, RetainedText<T>>,
+    next_id: u64,
+}
+
+impl<T> RetainedTexts<T> {
+    pub fn new() -> RetainedTexts<T> {
+        RetainedTexts { texts: BTreeMap::new(), next_id: 0 }
+    }
+
+    pub fn create(&mut self, x: f32, y: f32, size: f32, color: [f32; 4], text: &str) -> TextHandle {
+        let handle = TextHandle(self.next_id);
+        self.next_id += 1;
+        self.texts.insert(handle, RetainedText {
+            x,
+            y,
+            size,
+            color,
+            text:   text.to_string(),
+            layout: None,
+            cached: None,
+        });
+        handle
+    }
+
+    pub fn remove(&mut self, handle: TextHandle) {
+        self.texts.remove(&handle);
+    }
+
+    /// Panics if the text was removed
+    fn get_mut(&mut self, handle: TextHandle) -> &mut RetainedText<T> {
+        self.texts.get_mut(&handle).expect("text was removed")
+    }
+
+    pub fn set_content(&mut self, handle: TextHandle, text: &str) {
+        let retained = self.get_mut(handle);
+        if retained.text != text {
+            retained.text = text.to_string();
+            retained.layout = None;
+            retained.cached = None;
+        }
+    }
+
+    pub fn set_position(&mut self, handle: TextHandle, x: f32, y: f32) {
+        let retained = self.get_mut(handle);
+        if (retained.x, retained.y) != (x, y) {
+            retained.x = x;
+            retained.y = y;
+            retained.layout = None;
+            retained.cached = None;
+        }
+    }
+
+    pub fn set_size(&mut self, handle: TextHandle, size: f32) {
+        let retained = self.get_mut(handle);
+        if retained.size != size {
+            retained.size = size;
+            retained.layout = None;
+            retained.cached = None;
+        }
+    }
+
+    /// The layout is kept
+    pub fn set_color(&mut self, handle: TextHandle, color: [f32; 4]) {
+        let retained = self.get_mut(handle);
+        if retained.color != color {
+            retained.color = color;
+            if let Some((_, data)) = &mut retained.layout {
+                data.color = color;
+            }
+            retained.cached = None;
+        }
+    }
+
+    /// Throws out the renderer data of every text, e.g. when it depends on a setting that changed
+    pub fn clear_cached(&mut self) {
+        for retained in self.texts.values_mut() {
+            retained.cached = None;
+        }
+    }
+
+    /// Lays out texts that changed and queues the glyphs of every text, must be called before `GlyphCache::update`
+    pub fn queue_glyphs(&mut self, glyph_cache: &mut GlyphCache) {
+        let generation = glyph_cache.layout_generation();
+        for retained in self.texts.values_mut() {
+            let valid = matches!(&retained.layout, Some((layout_generation, _)) if *layout_generation == generation);
+            if valid {
+                glyph_cache.queue_glyphs(retained.data());
+            }
+            else {
+                let data = glyph_cache.queue_text(retained.x, retained.y, retained.size, retained.color, &retained.text);
+                retained.layout = Some((generation, data));
+                retained.cached = None;
+            }
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut RetainedText<T>> {
+        self.texts.values_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_kept_until_moved() {
+        let mut glyph_cache = GlyphCache::new();
+        let mut texts: RetainedTexts<()> = RetainedTexts::new();
+        let handle = texts.create(4.0, 24.0, 16.0, [1.0, 1.0, 1.0, 1.0], "Score");
+        texts.queue_glyphs(&mut glyph_cache);
+        let start = texts.get_mut(handle).data().glyphs[0].position().x;
+
+        texts.set_color(handle, [1.0, 0.0, 0.0, 1.0]);
+        let retained = texts.get_mut(handle);
+        assert!(retained.layout.is_some());
+        assert_eq!(retained.data().color, [1.0, 0.0, 0.0, 1.0]);
+
+        texts.set_position(handle, 14.0, 24.0);
+        assert!(texts.get_mut(handle).layout.is_none());
+        texts.queue_glyphs(&mut glyph_cache);
+        let moved = texts.get_mut(handle).data().glyphs[0].position().x;
+        assert_eq!(moved - start, 10.0);
+
+        texts.remove(handle);
+        assert!(texts.iter_mut().next().is_none());
+    }
+}
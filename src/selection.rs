@@ -0,0 +1,88 @@
+use crate::glyph_cache::{Solid, TextData};
+use crate::layout::{LaidOutText, Rect};
+
+use std::ops::Range;
+
+/// How `DrawText::queue_selected_text` draws a selection and caret.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SelectionStyle {
+    /// Drawn behind the selected text
+    pub highlight_color:     [f32; 4],
+    /// Selected text is drawn in this color instead of its own, e.g. to keep it readable on the highlight
+    pub selected_text_color: Option<[f32; 4]>,
+    pub caret_color:         [f32; 4],
+    /// In logical pixels
+    pub caret_width:         f32,
+    /// Seconds for the caret to blink on and off once, 0.0 never hides it
+    pub blink_period:        f32,
+    /// In logical pixels, IME preedit text is underlined in its own color and the clause being converted twice as thick
+    pub preedit_underline:   f32,
+}
+
+impl Default for SelectionStyle {
+    fn default() -> SelectionStyle {
+        SelectionStyle {
+            highlight_color:     [0.2, 0.4, 0.9, 1.0],
+            selected_text_color: Some([1.0, 1.0, 1.0, 1.0]),
+            caret_color:         [1.0, 1.0, 1.0, 1.0],
+            caret_width:         1.0,
+            blink_period:        1.0,
+            preedit_underline:   1.0,
+        }
+    }
+}
+
+impl SelectionStyle {
+    /// The caret is shown for the first half of every blink period
+    pub fn caret_visible(&self, blink_time: f32) -> bool {
+        self.blink_period <= 0.0 || blink_time.rem_euclid(self.blink_period) < self.blink_period / 2.0
+    }
+}
+
+/// Adds the selection highlight and caret to the laid out text
+pub(crate) fn selected_text(scale_factor: f32, text: &LaidOutText, selection: Range<usize>, caret: Option<usize>, blink_time: f32, style: &SelectionStyle) -> TextData {
+    let mut data = text.data.clone();
+
+    data.backgrounds = text.selection_rects(selection.clone()).into_iter()
+        .map(|rect| Solid::new(rect, scale_factor, style.highlight_color))
+        .collect();
+
+    if let Some(color) = style.selected_text_color {
+        data.recolor = Some((text.glyph_range(selection), color));
+    }
+
+    if let Some(caret) = caret {
+        if style.caret_visible(blink_time) {
+            let mut rect = text.caret_rect(caret);
+            rect.width = style.caret_width;
+            data.overlays.push(Solid::new(rect, scale_factor, style.caret_color));
+        }
+    }
+
+    data
+}
+
+/// Underlines the preedit text and places the caret at the end of the IME cursor, hiding it if there is no cursor
+pub(crate) fn preedit_text(scale_factor: f32, text: &LaidOutText, preedit: Range<usize>, cursor: Option<Range<usize>>, blink_time: f32, style: &SelectionStyle) -> TextData {
+    let mut data = text.data.clone();
+
+    let underline = |rect: Rect, width: f32| {
+        let rect = Rect { y: rect.y + rect.height - width, height: width, .. rect };
+        Solid::new(rect, scale_factor, data.color)
+    };
+    let mut overlays: Vec<Solid> = text.selection_rects(preedit).into_iter()
+        .map(|rect| underline(rect, style.preedit_underline))
+        .collect();
+
+    if let Some(cursor) = cursor {
+        overlays.extend(text.selection_rects(cursor.clone()).into_iter().map(|rect| underline(rect, style.preedit_underline * 2.0)));
+        if style.caret_visible(blink_time) {
+            let mut rect = text.caret_rect(cursor.end);
+            rect.width = style.caret_width;
+            overlays.push(Solid::new(rect, scale_factor, style.caret_color));
+        }
+    }
+
+    data.overlays = overlays;
+    data
+}
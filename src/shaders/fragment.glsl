@@ -2,10 +2,31 @@
 
 layout(location = 0) in vec2 v_tex_position;
 layout(location = 1) in vec4 v_color;
+// unused, but every output of the vertex shader needs a matching input
+layout(location = 2) in vec4 v_tex_bounds;
 layout(location = 0) out vec4 f_color;
 
 layout(set = 0, binding = 0) uniform sampler2D tex;
 
+layout(constant_id = 0) const float gamma = 1.0;
+layout(constant_id = 1) const float contrast = 0.0;
+layout(constant_id = 2) const int premultiply = 0;
+
+// Must match Gamma::adjust_coverage
+float adjust_coverage(float coverage, vec3 color) {
+    float luminance = clamp(dot(color, vec3(0.2126, 0.7152, 0.0722)), 0.0, 1.0);
+    coverage = pow(coverage, mix(1.0 / gamma, gamma, luminance));
+    return clamp(coverage + (coverage - 0.5) * contrast, 0.0, 1.0);
+}
+
 void main() {
-    f_color = v_color * texture(tex, v_tex_position)[0];
+    // Solid rectangles such as selection highlights have texture coordinates of -1.0, must match SOLID_UV
+    float coverage = v_tex_position.x < -0.5 ? 1.0 : adjust_coverage(texture(tex, v_tex_position)[0], v_color.rgb);
+    float alpha = v_color.a * coverage;
+    if (premultiply != 0) {
+        f_color = vec4(v_color.rgb * alpha, alpha);
+    }
+    else {
+        f_color = vec4(v_color.rgb, alpha);
+    }
 }
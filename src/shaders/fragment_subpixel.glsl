@@ -0,0 +1,65 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_position;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) in vec4 v_tex_bounds;
+layout(location = 0, index = 0) out vec4 f_color;
+layout(location = 0, index = 1) out vec4 f_blend;
+
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+layout(constant_id = 0) const float gamma = 1.0;
+layout(constant_id = 1) const float contrast = 0.0;
+layout(constant_id = 2) const int bgr = 0;
+
+// Must match LCD_FILTER
+const float lcd_filter[5] = float[](8.0 / 256.0, 77.0 / 256.0, 86.0 / 256.0, 77.0 / 256.0, 8.0 / 256.0);
+
+// Must match Gamma::adjust_coverage
+float adjust_coverage(float coverage, vec3 color) {
+    float luminance = clamp(dot(color, vec3(0.2126, 0.7152, 0.0722)), 0.0, 1.0);
+    coverage = pow(coverage, mix(1.0 / gamma, gamma, luminance));
+    return clamp(coverage + (coverage - 0.5) * contrast, 0.0, 1.0);
+}
+
+// Clamped so the filter never reads the neighbouring glyphs in the cache
+float texel(float offset) {
+    vec2 uv = v_tex_position + vec2(offset / float(textureSize(tex, 0).x), 0.0);
+    return texture(tex, clamp(uv, v_tex_bounds.xy, v_tex_bounds.zw))[0];
+}
+
+void main() {
+    // The cache holds glyphs at 3x horizontal resolution and v_tex_position is at the center of the middle subpixel.
+    // Each subpixel is filtered from the 5 texels around it.
+    float texels[7];
+    for (int i = 0; i < 7; i++) {
+        texels[i] = texel(float(i - 3));
+    }
+
+    vec3 coverage = vec3(0.0);
+    for (int c = 0; c < 3; c++) {
+        for (int k = 0; k < 5; k++) {
+            coverage[c] += lcd_filter[k] * texels[c + k];
+        }
+    }
+    if (bgr != 0) {
+        coverage = coverage.bgr;
+    }
+
+    // Solid rectangles such as selection highlights have texture coordinates of -1.0, must match SOLID_UV
+    if (v_tex_position.x < -0.5) {
+        coverage = vec3(1.0);
+    }
+
+    vec3 alpha = v_color.a * vec3(
+        adjust_coverage(coverage.r, v_color.rgb),
+        adjust_coverage(coverage.g, v_color.rgb),
+        adjust_coverage(coverage.b, v_color.rgb)
+    );
+    float average_alpha = (alpha.r + alpha.g + alpha.b) / 3.0;
+
+    // Color channels are blended as f_color * f_blend + dst * (1 - f_blend), or f_color * f_blend + dst when additive.
+    // This is already correct for premultiplied alpha as f_color is never multiplied by its alpha.
+    f_color = vec4(v_color.rgb, average_alpha);
+    f_blend = vec4(alpha, average_alpha);
+}
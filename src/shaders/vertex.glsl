@@ -1,13 +1,21 @@
 #version 450
 
-layout(location = 0) in vec2 position;
+layout(location = 0) in vec3 position;
 layout(location = 1) in vec2 tex_position;
-layout(location = 2) in vec4 color;
+layout(location = 2) in vec4 tex_bounds;
+layout(location = 3) in vec4 color;
 layout(location = 0) out vec2 v_tex_position;
 layout(location = 1) out vec4 v_color;
+layout(location = 2) out vec4 v_tex_bounds;
+
+// the view-projection matrix for text in world space, otherwise the identity matrix
+layout(push_constant) uniform PushConstants {
+    mat4 transform;
+} push_constants;
 
 void main() {
-    gl_Position = vec4(position, 0.0, 1.0);
+    gl_Position = push_constants.transform * vec4(position, 1.0);
     v_tex_position = tex_position;
     v_color = color;
+    v_tex_bounds = tex_bounds;
 }
@@ -0,0 +1,47 @@
+/// Where the text after a `'\t'` is placed, see `DrawText::set_tab_stops`.
+///
+/// Positions are in logical pixels from the start of the text.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TabStops {
+    /// Left aligned stops every this many logical pixels
+    Interval(f32),
+    /// Stops in increasing order of position, a tab past the last stop is as wide as a space
+    Stops(Vec<TabStop>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TabStop {
+    pub position:  f32,
+    pub alignment: TabAlignment,
+}
+
+/// Which part of the text after a tab lines up with its tab stop
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TabAlignment {
+    /// The text starts at the stop
+    Left,
+    /// The text, up to the next tab or the end of the line, ends at the stop
+    Right,
+    /// The first `'.'` of the text is at the stop, or its end if it has none, so columns of numbers line up
+    Decimal,
+}
+
+impl Default for TabStops {
+    fn default() -> TabStops {
+        TabStops::Interval(64.0)
+    }
+}
+
+impl TabStops {
+    /// The first stop after `x`
+    pub(crate) fn next_stop(&self, x: f32) -> Option<TabStop> {
+        match self {
+            TabStops::Interval(interval) if *interval > 0.0 => {
+                let position = ((x / interval).floor() + 1.0) * interval;
+                Some(TabStop { position, alignment: TabAlignment::Left })
+            }
+            TabStops::Interval(_) => None,
+            TabStops::Stops(stops) => stops.iter().find(|stop| stop.position > x).cloned(),
+        }
+    }
+}
@@ -0,0 +1,609 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::layout::{Layout, LaidOutText, Rect};
+use crate::selection::SelectionStyle;
+
+use std::ops::Range;
+use std::time::Instant;
+
+/// Where `TextInput` copies to and pastes from, e.g. the system clipboard.
+pub trait Clipboard {
+    fn get(&mut self) -> Option<String>;
+    fn set(&mut self, text: String);
+}
+
+/// Where `TextInput::queue` queues its text, implemented by `DrawText` and `CpuDrawText`.
+pub trait QueueText {
+    fn layout(&self) -> Layout;
+    fn queue_laid_out_text(&mut self, text: &mut LaidOutText);
+    fn queue_selected_text(&mut self, text: &mut LaidOutText, selection: Range<usize>, caret: Option<usize>, blink_time: f32, style: &SelectionStyle);
+    fn queue_preedit_text(&mut self, text: &mut LaidOutText, preedit: Range<usize>, cursor: Option<Range<usize>>, blink_time: f32, style: &SelectionStyle);
+}
+
+/// An editable text field that owns its text, caret and selection and is drawn through `DrawText` or `CpuDrawText`.
+///
+/// Carets and selections are byte indices into the text that always lie on grapheme cluster boundaries.
+/// With the `winit` feature enabled, `handle_event` edits the text in response to keyboard events.
+pub struct TextInput {
+    text:        String,
+    caret:       usize,
+    /// The other end of the selection, equal to `caret` when nothing is selected
+    anchor:      usize,
+    multiline:   bool,
+    focused:     bool,
+    clipboard:   Option<Box<dyn Clipboard>>,
+    /// When the caret last moved, the caret is always visible right after moving
+    blink_start: Instant,
+    /// From the last call to `queue`, used to move the caret between lines
+    laid_out:    Option<LaidOutText>,
+    /// The IME composition shown at the caret and the IME cursor as byte indices into it
+    preedit:     Option<(String, Option<Range<usize>>)>,
+    /// Where the last call to `queue` drew the caret
+    ime_rect:    Option<Rect>,
+    pub x:       f32,
+    pub y:       f32,
+    pub size:    f32,
+    pub color:   [f32; 4],
+    pub style:   SelectionStyle,
+    #[cfg(feature = "winit")]
+    modifiers:   winit::event::ModifiersState,
+}
+
+impl TextInput {
+    /// A focused, empty, single line input at `(x, y)`
+    pub fn new(x: f32, y: f32, size: f32, color: [f32; 4]) -> TextInput {
+        TextInput {
+            text:        String::new(),
+            caret:       0,
+            anchor:      0,
+            multiline:   false,
+            focused:     true,
+            clipboard:   None,
+            blink_start: Instant::now(),
+            laid_out:    None,
+            preedit:     None,
+            ime_rect:    None,
+            x,
+            y,
+            size,
+            color,
+            style:       SelectionStyle::default(),
+            #[cfg(feature = "winit")]
+            modifiers:   winit::event::ModifiersState::empty(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text and places the caret at its end
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.set_caret(self.text.len(), false);
+    }
+
+    pub fn multiline(&self) -> bool {
+        self.multiline
+    }
+
+    /// When true, enter inserts line breaks and up and down move between lines
+    pub fn set_multiline(&mut self, multiline: bool) {
+        self.multiline = multiline;
+    }
+
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// The caret and selection are only drawn while focused
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.blink_start = Instant::now();
+    }
+
+    /// Enables copy, cut and paste, without a clipboard they do nothing
+    pub fn set_clipboard(&mut self, clipboard: Box<dyn Clipboard>) {
+        self.clipboard = Some(clipboard);
+    }
+
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// Moves the caret to the byte index, rounded down to a grapheme cluster boundary.
+    /// When `extend_selection` is true the selection is extended to the caret, otherwise it is cleared.
+    pub fn set_caret(&mut self, index: usize, extend_selection: bool) {
+        self.caret = self.boundary_at_or_before(index.min(self.text.len()));
+        if !extend_selection {
+            self.anchor = self.caret;
+        }
+        self.blink_start = Instant::now();
+    }
+
+    pub fn selection(&self) -> Range<usize> {
+        self.caret.min(self.anchor)..self.caret.max(self.anchor)
+    }
+
+    pub fn selected_text(&self) -> &str {
+        &self.text[self.selection()]
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = 0;
+        self.caret = self.text.len();
+        self.blink_start = Instant::now();
+    }
+
+    /// Replaces the selection with `text` and clears the preedit string, line breaks are removed unless the input is multiline
+    pub fn insert(&mut self, text: &str) {
+        let text = if self.multiline {
+            text.to_string()
+        }
+        else {
+            text.replace(['\n', '\r'], "")
+        };
+        let selection = self.selection();
+        self.preedit = None;
+        self.text.replace_range(selection.clone(), &text);
+        self.set_caret(selection.start + text.len(), false);
+    }
+
+    /// Deletes the selection, or the grapheme cluster before the caret (a word with `word` set) if nothing is selected
+    pub fn delete_backward(&mut self, word: bool) {
+        if self.anchor == self.caret {
+            let target = if word { self.previous_word(self.caret) } else { self.previous_boundary(self.caret) };
+            self.anchor = target;
+        }
+        self.insert("");
+    }
+
+    /// Deletes the selection, or the grapheme cluster after the caret (a word with `word` set) if nothing is selected
+    pub fn delete_forward(&mut self, word: bool) {
+        if self.anchor == self.caret {
+            let target = if word { self.next_word(self.caret) } else { self.next_boundary(self.caret) };
+            self.anchor = target;
+        }
+        self.insert("");
+    }
+
+    /// Moves the caret one grapheme cluster or word to the left, collapsing the selection to its start unless extending it
+    pub fn move_left(&mut self, word: bool, extend_selection: bool) {
+        let target = if !extend_selection && self.anchor != self.caret && !word {
+            self.selection().start
+        }
+        else if word {
+            self.previous_word(self.caret)
+        }
+        else {
+            self.previous_boundary(self.caret)
+        };
+        self.set_caret(target, extend_selection);
+    }
+
+    /// Moves the caret one grapheme cluster or word to the right, collapsing the selection to its end unless extending it
+    pub fn move_right(&mut self, word: bool, extend_selection: bool) {
+        let target = if !extend_selection && self.anchor != self.caret && !word {
+            self.selection().end
+        }
+        else if word {
+            self.next_word(self.caret)
+        }
+        else {
+            self.next_boundary(self.caret)
+        };
+        self.set_caret(target, extend_selection);
+    }
+
+    /// Moves the caret to the start of its line, or of the text with `document` set
+    pub fn move_home(&mut self, document: bool, extend_selection: bool) {
+        let target = if document { 0 } else { self.text[..self.caret].rfind('\n').map(|i| i + 1).unwrap_or(0) };
+        self.set_caret(target, extend_selection);
+    }
+
+    /// Moves the caret to the end of its line, or of the text with `document` set
+    pub fn move_end(&mut self, document: bool, extend_selection: bool) {
+        let target = if document {
+            self.text.len()
+        }
+        else {
+            let end = self.text[self.caret..].find('\n').map(|i| self.caret + i).unwrap_or(self.text.len());
+            // keep the caret before a "\r\n" line break
+            if self.text[..end].ends_with('\r') { end - 1 } else { end }
+        };
+        self.set_caret(target, extend_selection);
+    }
+
+    /// Moves the caret to the nearest position on the line above, as laid out by the last call to `queue`
+    pub fn move_up(&mut self, extend_selection: bool) {
+        self.move_vertically(-1.0, extend_selection);
+    }
+
+    /// Moves the caret to the nearest position on the line below, as laid out by the last call to `queue`
+    pub fn move_down(&mut self, extend_selection: bool) {
+        self.move_vertically(1.0, extend_selection);
+    }
+
+    fn move_vertically(&mut self, direction: f32, extend_selection: bool) {
+        let target = match &self.laid_out {
+            Some(laid_out) => {
+                let rect = laid_out.caret_rect(self.grapheme_index(self.caret));
+                let y = rect.y + rect.height / 2.0 + rect.height * direction;
+                self.byte_index(laid_out.hit_test(rect.x, y).caret())
+            }
+            None => return,
+        };
+        self.set_caret(target, extend_selection);
+    }
+
+    pub fn copy(&mut self) {
+        let selected = self.selected_text().to_string();
+        if let Some(clipboard) = &mut self.clipboard {
+            if !selected.is_empty() {
+                clipboard.set(selected);
+            }
+        }
+    }
+
+    pub fn cut(&mut self) {
+        if self.clipboard.is_some() {
+            self.copy();
+            self.insert("");
+        }
+    }
+
+    pub fn paste(&mut self) {
+        if let Some(text) = self.clipboard.as_mut().and_then(|clipboard| clipboard.get()) {
+            self.insert(&text);
+        }
+    }
+
+    /// Shows an IME composition (preedit) string at the caret in place of the selection, until it is committed with `insert` or cleared.
+    /// `cursor` is the IME's cursor as byte indices into `preedit`, the caret is hidden when it is `None`.
+    /// Indices past the end of `preedit` or within a char are moved back to the nearest char boundary.
+    pub fn set_preedit(&mut self, preedit: &str, cursor: Option<Range<usize>>) {
+        let char_boundary = |index: usize| (0..=index.min(preedit.len())).rev().find(|&i| preedit.is_char_boundary(i)).unwrap_or(0);
+        let cursor = cursor.map(|cursor| {
+            let start = char_boundary(cursor.start);
+            start..char_boundary(cursor.end).max(start)
+        });
+        self.preedit = if preedit.is_empty() {
+            None
+        }
+        else {
+            Some((preedit.to_string(), cursor))
+        };
+        self.blink_start = Instant::now();
+    }
+
+    pub fn clear_preedit(&mut self) {
+        self.preedit = None;
+    }
+
+    pub fn preedit(&self) -> Option<&str> {
+        self.preedit.as_ref().map(|(preedit, _)| preedit.as_str())
+    }
+
+    /// The caret rectangle in logical pixels as drawn by the last call to `queue`, including the preedit string.
+    /// Pass it to the IME so it places its candidate window next to the text, e.g. with winit's `Window::set_ime_position`.
+    pub fn ime_rect(&self) -> Option<Rect> {
+        self.ime_rect
+    }
+
+    /// Lays out the text with the settings of `layout`, keeping it for moving the caret between lines and for `laid_out`
+    pub fn lay_out(&mut self, layout: &Layout) -> &LaidOutText {
+        let laid_out = layout.layout_text(self.x, self.y, self.size, self.color, &self.text);
+        self.laid_out.insert(laid_out)
+    }
+
+    /// The text as laid out by the last call to `queue` or `lay_out`, e.g. for hit testing mouse clicks
+    pub fn laid_out(&self) -> Option<&LaidOutText> {
+        self.laid_out.as_ref()
+    }
+
+    /// Queues the text along with its selection and blinking caret, or its preedit string, while focused
+    pub fn queue<Q>(&mut self, draw_text: &mut Q) where Q: QueueText {
+        let layout = draw_text.layout();
+        self.lay_out(&layout);
+        self.ime_rect = None;
+        if let (true, Some((preedit, cursor))) = (self.focused, &self.preedit) {
+            // the preedit string replaces the selection until it is committed
+            let selection = self.selection();
+            let composed = format!("{}{}{}", &self.text[..selection.start], preedit, &self.text[selection.end..]);
+            let mut composed_laid_out = layout.layout_text(self.x, self.y, self.size, self.color, &composed);
+
+            let start = self.grapheme_index(selection.start);
+            let grapheme_index = |byte_index: usize| start + preedit[..byte_index].graphemes(true).count();
+            let preedit_graphemes = start..grapheme_index(preedit.len());
+            let cursor = cursor.as_ref().map(|cursor| grapheme_index(cursor.start)..grapheme_index(cursor.end));
+
+            self.ime_rect = Some(composed_laid_out.caret_rect(cursor.as_ref().map(|cursor| cursor.end).unwrap_or(preedit_graphemes.end)));
+            let blink_time = self.blink_start.elapsed().as_secs_f32();
+            draw_text.queue_preedit_text(&mut composed_laid_out, preedit_graphemes, cursor, blink_time, &self.style);
+        }
+        else if self.focused {
+            let selection = self.grapheme_index(self.selection().start)..self.grapheme_index(self.selection().end);
+            let caret = self.grapheme_index(self.caret);
+            let laid_out = self.laid_out.as_mut().unwrap();
+            self.ime_rect = Some(laid_out.caret_rect(caret));
+            let blink_time = self.blink_start.elapsed().as_secs_f32();
+            draw_text.queue_selected_text(laid_out, selection, Some(caret), blink_time, &self.style);
+        }
+        else {
+            draw_text.queue_laid_out_text(self.laid_out.as_mut().unwrap());
+        }
+    }
+
+    /// Edits the text in response to a keyboard event, returns true if the event was used.
+    /// Text committed by an IME arrives as `ReceivedCharacter` events and is inserted like typed text.
+    #[cfg(feature = "winit")]
+    pub fn handle_event(&mut self, event: &winit::event::WindowEvent) -> bool {
+        use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                false
+            }
+            WindowEvent::ReceivedCharacter(c) if self.focused => {
+                // control characters such as backspace and enter also arrive as key presses
+                if c.is_control() || self.shortcut_held() {
+                    return false;
+                }
+                self.insert(c.encode_utf8(&mut [0; 4]));
+                true
+            }
+            WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. }, .. } if self.focused => {
+                let shift = self.modifiers.shift();
+                let shortcut = self.shortcut_held();
+                let word = self.modifiers.ctrl() || self.modifiers.alt();
+                match key {
+                    VirtualKeyCode::Back   => self.delete_backward(word),
+                    VirtualKeyCode::Delete => self.delete_forward(word),
+                    VirtualKeyCode::Left   => self.move_left(word, shift),
+                    VirtualKeyCode::Right  => self.move_right(word, shift),
+                    VirtualKeyCode::Up     if self.multiline => self.move_up(shift),
+                    VirtualKeyCode::Down   if self.multiline => self.move_down(shift),
+                    VirtualKeyCode::Home   => self.move_home(shortcut, shift),
+                    VirtualKeyCode::End    => self.move_end(shortcut, shift),
+                    VirtualKeyCode::Return |
+                    VirtualKeyCode::NumpadEnter if self.multiline => self.insert("\n"),
+                    VirtualKeyCode::A if shortcut => self.select_all(),
+                    VirtualKeyCode::C if shortcut => self.copy(),
+                    VirtualKeyCode::X if shortcut => self.cut(),
+                    VirtualKeyCode::V if shortcut => self.paste(),
+                    _ => return false,
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the held modifiers make keys shortcuts rather than typed text.
+    /// AltGr is reported as ctrl and alt on Windows and types characters such as `@` and `€` on many layouts.
+    #[cfg(feature = "winit")]
+    fn shortcut_held(&self) -> bool {
+        (self.modifiers.ctrl() && !self.modifiers.alt()) || self.modifiers.logo()
+    }
+
+    /// The grapheme index of the grapheme cluster starting at `byte_index`
+    fn grapheme_index(&self, byte_index: usize) -> usize {
+        self.text[..byte_index].graphemes(true).count()
+    }
+
+    /// The byte index of the grapheme cluster at `grapheme_index`, or the end of the text
+    fn byte_index(&self, grapheme_index: usize) -> usize {
+        self.text.grapheme_indices(true).nth(grapheme_index).map(|(i, _)| i).unwrap_or(self.text.len())
+    }
+
+    fn boundary_at_or_before(&self, index: usize) -> usize {
+        if index >= self.text.len() {
+            return self.text.len();
+        }
+        self.text.grapheme_indices(true).map(|(i, _)| i).take_while(|&i| i <= index).last().unwrap_or(0)
+    }
+
+    fn previous_boundary(&self, index: usize) -> usize {
+        self.text[..index].grapheme_indices(true).next_back().map(|(i, _)| i).unwrap_or(0)
+    }
+
+    fn next_boundary(&self, index: usize) -> usize {
+        self.text[index..].graphemes(true).next().map(|grapheme| index + grapheme.len()).unwrap_or(index)
+    }
+
+    /// The start of the word before `index`, skipping whitespace and punctuation
+    fn previous_word(&self, index: usize) -> usize {
+        self.text[..index].unicode_word_indices().next_back().map(|(i, _)| i).unwrap_or(0)
+    }
+
+    /// The end of the word after `index`, skipping whitespace and punctuation
+    fn next_word(&self, index: usize) -> usize {
+        self.text[index..].unicode_word_indices().next().map(|(i, word)| index + i + word.len()).unwrap_or(self.text.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CpuDrawText;
+
+    fn input(text: &str) -> TextInput {
+        let mut input = TextInput::new(0.0, 0.0, 16.0, [1.0; 4]);
+        input.set_text(text);
+        input
+    }
+
+    #[test]
+    fn insert() {
+        let mut input = input("helo");
+        input.set_caret(3, false);
+        input.insert("l");
+        assert_eq!(input.text(), "hello");
+        assert_eq!(input.caret(), 4);
+
+        input.insert("\r\n!");
+        assert_eq!(input.text(), "hell!o");
+
+        input.set_multiline(true);
+        input.insert("\n");
+        assert_eq!(input.text(), "hell!\no");
+        assert_eq!(input.caret(), 6);
+    }
+
+    #[test]
+    fn replace_selection() {
+        let mut input = input("hello world");
+        input.set_caret(6, false);
+        input.set_caret(11, true);
+        assert_eq!(input.selected_text(), "world");
+
+        input.insert("there");
+        assert_eq!(input.text(), "hello there");
+        assert_eq!(input.selection(), 11..11);
+
+        // a selection made right to left is replaced the same way
+        input.set_caret(5, true);
+        input.insert("");
+        assert_eq!(input.text(), "hello");
+        assert_eq!(input.caret(), 5);
+    }
+
+    #[test]
+    fn delete() {
+        let mut input = input("one two three");
+        input.delete_backward(false);
+        assert_eq!(input.text(), "one two thre");
+        input.delete_backward(true);
+        assert_eq!(input.text(), "one two ");
+
+        input.set_caret(0, false);
+        input.delete_forward(false);
+        assert_eq!(input.text(), "ne two ");
+        input.delete_forward(true);
+        assert_eq!(input.text(), " two ");
+
+        input.select_all();
+        input.delete_forward(false);
+        assert_eq!(input.text(), "");
+        input.delete_backward(false);
+        assert_eq!(input.text(), "");
+    }
+
+    #[test]
+    fn delete_grapheme() {
+        // e with a combining acute accent, then a family emoji joined by zero width joiners
+        let mut input = input("e\u{301}\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}");
+        input.delete_backward(false);
+        assert_eq!(input.text(), "e\u{301}");
+        input.delete_backward(false);
+        assert_eq!(input.text(), "");
+    }
+
+    #[test]
+    fn move_by_grapheme() {
+        let mut input = input("ae\u{301}b");
+        input.set_caret(0, false);
+        input.move_right(false, false);
+        assert_eq!(input.caret(), 1);
+        input.move_right(false, false);
+        assert_eq!(input.caret(), 4);
+        input.move_left(false, false);
+        assert_eq!(input.caret(), 1);
+
+        // carets inside a grapheme cluster are moved to its start
+        input.set_caret(2, false);
+        assert_eq!(input.caret(), 1);
+    }
+
+    #[test]
+    fn move_by_word() {
+        let mut input = input("one, two  three");
+        input.move_left(true, false);
+        assert_eq!(input.caret(), 10);
+        input.move_left(true, false);
+        assert_eq!(input.caret(), 5);
+        input.move_left(true, false);
+        assert_eq!(input.caret(), 0);
+
+        input.move_right(true, false);
+        assert_eq!(input.caret(), 3);
+        input.move_right(true, true);
+        assert_eq!(input.caret(), 8);
+        assert_eq!(input.selected_text(), ", two");
+    }
+
+    #[test]
+    fn collapse_selection() {
+        let mut input = input("hello");
+        input.set_caret(1, false);
+        input.set_caret(4, true);
+        input.move_left(false, false);
+        assert_eq!(input.selection(), 1..1);
+
+        input.set_caret(4, true);
+        input.move_right(false, false);
+        assert_eq!(input.selection(), 4..4);
+    }
+
+    #[test]
+    fn multibyte_preedit_cursor() {
+        let mut input = input("");
+        input.set_preedit("にほ", Some(0..1));
+        assert_eq!(input.preedit, Some(("にほ".to_string(), Some(0..0))));
+
+        input.set_preedit("にほ", Some(4..100));
+        assert_eq!(input.preedit, Some(("にほ".to_string(), Some(3..6))));
+
+        // a cursor ending before it starts
+        input.set_preedit("にほ", Some(Range { start: 5, end: 2 }));
+        assert_eq!(input.preedit, Some(("にほ".to_string(), Some(3..3))));
+    }
+
+    #[test]
+    fn move_between_lines() {
+        let mut draw_text = CpuDrawText::new(64, 64);
+        let mut input = TextInput::new(0.0, 20.0, 16.0, [1.0; 4]);
+        input.set_multiline(true);
+        input.set_text("abc\ndef\ng");
+
+        // nothing laid out yet
+        input.set_caret(1, false);
+        input.move_down(false);
+        assert_eq!(input.caret(), 1);
+
+        input.queue(&mut draw_text);
+        input.move_down(false);
+        assert_eq!(input.caret(), 5);
+        input.move_down(true);
+        assert_eq!(input.caret(), 9);
+        assert_eq!(input.selection(), 5..9);
+        input.move_up(false);
+        input.move_up(false);
+        assert_eq!(input.caret(), 1);
+
+        // the first line has no line above it
+        input.move_up(false);
+        assert_eq!(input.caret(), 1);
+    }
+
+    #[test]
+    fn queue_preedit() {
+        let mut draw_text = CpuDrawText::new(64, 32);
+        let mut input = TextInput::new(0.0, 20.0, 16.0, [1.0; 4]);
+        input.set_text("ab");
+        input.set_caret(1, false);
+        input.set_preedit("xy", Some(1..1));
+        input.queue(&mut draw_text);
+
+        // the caret is at the IME cursor within the composed text, the text itself is unchanged until committed
+        let composed = draw_text.layout().layout_text(0.0, 20.0, 16.0, [1.0; 4], "axyb");
+        assert_eq!(input.ime_rect(), Some(composed.caret_rect(2)));
+        assert_eq!(input.text(), "ab");
+        assert!(draw_text.render().iter().any(|value| *value != 0));
+
+        input.set_focused(false);
+        input.queue(&mut draw_text);
+        assert_eq!(input.ime_rect(), None);
+    }
+}
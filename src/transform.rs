@@ -0,0 +1,73 @@
+/// A 2D affine transform of screen space, used to rotate, scale or skew text.
+///
+/// Screen space has y pointing down, so positive rotations are clockwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    /// Column major: `x' = m[0][0] * x + m[1][0] * y + m[2][0]` and `y' = m[0][1] * x + m[1][1] * y + m[2][1]`
+    pub matrix: [[f32; 2]; 3],
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform { matrix: [[1.0, 0.0], [0.0, 1.0], [0.0, 0.0]] }
+    }
+
+    pub fn translation(x: f32, y: f32) -> Transform {
+        Transform { matrix: [[1.0, 0.0], [0.0, 1.0], [x, y]] }
+    }
+
+    pub fn rotation(radians: f32) -> Transform {
+        let (sin, cos) = radians.sin_cos();
+        Transform { matrix: [[cos, sin], [-sin, cos], [0.0, 0.0]] }
+    }
+
+    pub fn scale(x: f32, y: f32) -> Transform {
+        Transform { matrix: [[x, 0.0], [0.0, y], [0.0, 0.0]] }
+    }
+
+    /// Shears x by the tangent of `x_radians` for every unit of y and y by the tangent of `y_radians` for every unit of x
+    pub fn skew(x_radians: f32, y_radians: f32) -> Transform {
+        Transform { matrix: [[1.0, y_radians.tan()], [x_radians.tan(), 1.0], [0.0, 0.0]] }
+    }
+
+    /// Returns a transform that applies `self` and then `other`
+    pub fn then(&self, other: &Transform) -> Transform {
+        let a = &self.matrix;
+        let b = &other.matrix;
+        Transform {
+            matrix: [
+                [b[0][0] * a[0][0] + b[1][0] * a[0][1], b[0][1] * a[0][0] + b[1][1] * a[0][1]],
+                [b[0][0] * a[1][0] + b[1][0] * a[1][1], b[0][1] * a[1][0] + b[1][1] * a[1][1]],
+                [b[0][0] * a[2][0] + b[1][0] * a[2][1] + b[2][0], b[0][1] * a[2][0] + b[1][1] * a[2][1] + b[2][1]],
+            ]
+        }
+    }
+
+    /// Returns a transform that applies `self` with `(x, y)` as the origin, e.g. to rotate text around its anchor
+    pub fn around(&self, x: f32, y: f32) -> Transform {
+        Transform::translation(-x, -y).then(self).then(&Transform::translation(x, y))
+    }
+
+    pub fn transform_point(&self, point: [f32; 2]) -> [f32; 2] {
+        let m = &self.matrix;
+        [
+            m[0][0] * point[0] + m[1][0] * point[1] + m[2][0],
+            m[0][1] * point[0] + m[1][1] * point[1] + m[2][1],
+        ]
+    }
+
+    /// How much the x and y axes are stretched, which is how much larger glyphs need to be rasterized to stay crisp
+    pub(crate) fn axis_scales(&self) -> [f32; 2] {
+        let m = &self.matrix;
+        [
+            (m[0][0] * m[0][0] + m[0][1] * m[0][1]).sqrt().max(0.01),
+            (m[1][0] * m[1][0] + m[1][1] * m[1][1]).sqrt().max(0.01),
+        ]
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}